@@ -1,12 +1,17 @@
-
-
 pub mod events;
+pub mod pad_settings;
+pub mod render;
 pub mod state;
 pub mod ui;
+pub mod velocity;
 
 use crate::app::state::{AppCommand, AppState, AudioCommand};
+use crate::audio::capture::pipewire_backend::PipeWireCapture;
 use crate::audio::capture::run_capture_loop;
 use crate::audio::player::{self, run_kira_loop};
+use crate::audio::routing::{PlaybackSink, run_routing_loop};
+use crate::config::Config;
+use crate::hardware::controller::Controller;
 use log::{error, info};
 use push2::Push2;
 use std::error::Error;
@@ -14,18 +19,19 @@ use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
 
-pub async fn run() -> Result<(), Box<dyn Error>> {
+pub async fn run(config: Config) -> Result<(), Box<dyn Error>> {
     // 1. Setup Channels
     let (audio_tx, audio_rx) = mpsc::channel::<AudioCommand>();
     let (app_tx, app_rx) = mpsc::channel::<AppCommand>();
     let (kira_tx, kira_rx) = mpsc::channel::<player::KiraCommand>();
+    let (routing_tx, routing_rx) = pipewire::channel::channel::<PlaybackSink>();
 
-    // 2. Spawn Audio Threads
-    spawn_audio_threads(audio_rx, app_tx.clone(), kira_rx);
+    // 2. Initialize State
+    let mut app_state = AppState::new(config, audio_tx, kira_tx, app_tx.clone(), routing_tx)?;
 
-    // 3. Initialize Hardware & State
+    // 3. Spawn Audio Threads & Initialize Hardware
+    spawn_audio_threads(&app_state, audio_rx, app_tx.clone(), kira_rx, routing_rx);
     let mut push2 = Push2::new()?;
-    let mut app_state = AppState::new(audio_tx, kira_tx)?;
 
     // 4. Initial Hardware Setup
     initial_hardware_setup(&mut push2, &mut app_state)?;
@@ -33,42 +39,93 @@ pub async fn run() -> Result<(), Box<dyn Error>> {
     info!("System Ready. Starting Main Loop.");
 
     // 5. Main Loop
+    let result = run_main_loop(&mut push2, &mut app_state, &app_rx).await;
+    // Edits still inside the settle delay would be lost otherwise.
+    app_state.pad_settings.flush();
+    result
+}
+
+/// Runs until the display fails, an event handler errors or the process is interrupted.
+async fn run_main_loop(
+    push2: &mut impl Controller,
+    state: &mut AppState,
+    app_rx: &mpsc::Receiver<AppCommand>,
+) -> Result<(), Box<dyn Error>> {
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
     loop {
-        events::handle_incoming_events(&mut push2, &mut app_state, &app_rx).await?;
-        if let Err(e) = ui::draw_screen(&mut push2, &mut app_state) {
+        events::handle_incoming_events(push2, state, app_rx).await?;
+        if let Err(e) = ui::draw_screen(push2, state) {
             error!("Display error: {}", e);
-            break;
+            return Ok(());
         }
         // Maintain frame rate
-        tokio::time::sleep(Duration::from_millis(1000 / 60)).await;
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_millis(1000 / 60)) => {}
+            _ = &mut ctrl_c => {
+                info!("Interrupted, shutting down");
+                return Ok(());
+            }
+        }
     }
-    Ok(())
 }
 
+/// Starts capture, playback and routing. They share the state's meters and master bus.
 fn spawn_audio_threads(
+    state: &AppState,
     audio_rx: mpsc::Receiver<AudioCommand>,
     app_tx: mpsc::Sender<AppCommand>,
     kira_rx: mpsc::Receiver<player::KiraCommand>,
+    routing_rx: pipewire::channel::Receiver<PlaybackSink>,
 ) {
+    let config = &state.config;
+    let capture_config = config.capture;
+    let input_monitor = state.input_monitor.clone();
+    let capture_app_tx = app_tx.clone();
     thread::spawn(move || {
         info!("Audio capture thread started...");
-        if let Err(e) = run_capture_loop(audio_rx, app_tx) {
+        if let Err(e) = run_capture_loop(
+            PipeWireCapture,
+            audio_rx,
+            capture_app_tx,
+            capture_config,
+            input_monitor,
+        ) {
             error!("Audio capture thread failed: {}", e);
         }
     });
 
+    let kira_app_tx = app_tx.clone();
+    let master = state.master.clone();
+    let meters = state.meters.clone();
     thread::spawn(move || {
         info!("Kira audio thread started...");
-        if let Err(e) = run_kira_loop(kira_rx) {
+        if let Err(e) = run_kira_loop(kira_rx, kira_app_tx, master, meters) {
             error!("Kira audio thread failed: {}", e);
         }
     });
+
+    let routing_config = config.routing.clone();
+    thread::spawn(move || {
+        info!("PipeWire routing thread started...");
+        if let Err(e) = run_routing_loop(routing_rx, app_tx, routing_config) {
+            error!("PipeWire routing thread failed: {}", e);
+        }
+    });
 }
 
-fn initial_hardware_setup(push2: &mut Push2, state: &mut AppState) -> Result<(), Box<dyn Error>> {
+pub fn initial_hardware_setup(
+    push2: &mut impl Controller,
+    state: &mut AppState,
+) -> Result<(), Box<dyn Error>> {
     state.update_pad_lights(push2)?;
+    state.update_bank_lights(push2)?;
     // Set initial button states
-    push2.set_button_light(push2::ControlName::Mute, push2::Push2Colors::GREEN_PALE)?;
-    push2.set_button_light(push2::ControlName::Solo, push2::Push2Colors::GREEN_PALE)?;
+    let button_on = state.config.colors.button_on;
+    push2.set_button_light(push2::ControlName::Mute, button_on)?;
+    push2.set_button_light(push2::ControlName::Solo, button_on)?;
+    events::update_accent_light(push2, state)?;
+    events::update_page_lights(push2, state)?;
+    events::update_audio_routing(state);
     Ok(())
 }
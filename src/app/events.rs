@@ -1,24 +1,41 @@
-use crate::app::state::{
-    AppCommand, AppState, AudioCommand, BUTTON_LIGHT_ON, COLOR_HAS_FILE, COLOR_OFF, COLOR_PLAYING,
-    COLOR_RECORDING, COLOR_SELECTED,
+use crate::app::pad_settings::{
+    CHOKE_GROUP_COUNT, MAX_TEMPO, MAX_VOICES, MIN_TEMPO, PadMode, PadSettings, PitchMode,
 };
-use crate::audio::player::{self, KiraCommand, PlaybackSink};
+use crate::app::render::{self, RENDER_SETTLE_DELAY};
+use crate::app::state::{AppCommand, AppState, AudioCommand, BANK_COUNT, EditPage, RenderParams};
+use crate::app::velocity::VelocityCurve;
+use crate::audio::capture::gain::MAX_INPUT_GAIN_DB;
+use crate::audio::effects::{MAX_CUTOFF_HZ, MAX_DRIVE_DB, MIN_CUTOFF_HZ};
+use crate::audio::envelope::{MAX_ATTACK_MS, MAX_HOLD_MS, MAX_RELEASE_MS};
+use crate::audio::player::{self, KiraCommand};
+use crate::audio::routing::PlaybackSink;
+use crate::audio::stretch::StretchParams;
+use crate::audio::trim::TrimPoints;
+use crate::hardware::controller::Controller;
 use kira::sound::static_sound::{StaticSoundData, StaticSoundSettings};
 use log::{error, info};
-use push2::{ControlName, EncoderName, Push2, Push2Event};
+use push2::{ControlName, EncoderName, Push2Event};
 use std::sync::mpsc::Receiver;
 use std::time;
 use tokio::fs as tokio_fs;
 
+// Page buttons step one bank, octave buttons jump a row of four.
+const BANKS_PER_OCTAVE: i32 = 4;
+const TICKS_PER_STEP: i32 = 8;
+// Shorter trims would hand the engine an empty slice.
+const MIN_TRIM_SECONDS: f64 = 0.001;
+
 pub async fn handle_incoming_events(
-    push2: &mut Push2,
+    push2: &mut impl Controller,
     state: &mut AppState,
     app_rx: &Receiver<AppCommand>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // 1. Hardware Events
     while let Some(event) = push2.poll_event() {
         match event {
-            Push2Event::PadPressed { coord, .. } => handle_pad_pressed(push2, state, coord).await?,
+            Push2Event::PadPressed { coord, velocity } => {
+                handle_pad_pressed(push2, state, coord, velocity).await?
+            }
             Push2Event::PadReleased { coord } => handle_pad_released(push2, state, coord)?,
             Push2Event::ButtonPressed { name, .. } => handle_button_pressed(push2, state, name)?,
             Push2Event::ButtonReleased { name } => handle_button_released(push2, state, name)?,
@@ -33,15 +50,25 @@ pub async fn handle_incoming_events(
     while let Ok(app_event) = app_rx.try_recv() {
         handle_app_command(push2, state, app_event)?;
     }
+
+    // 3. Follow the selected pad's toggles on the button LEDs
+    update_reverse_light(push2, state)?;
+
+    // 4. Render edited pads in the background once their edits settle
+    schedule_renders(state);
+
+    // 5. Persist encoder edits once they have settled
+    state.pad_settings.save_if_settled();
     Ok(())
 }
 
 async fn handle_pad_pressed(
-    push2: &mut Push2,
+    push2: &mut impl Controller,
     state: &mut AppState,
     coord: push2::PadCoord,
+    velocity: u8,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let Some(address) = push2.button_map.get_note_address(coord) else {
+    let Some(address) = push2.pad_address(coord) else {
         return Ok(());
     };
     let Some(path) = state.pad_files.get(&address).cloned() else {
@@ -50,16 +77,20 @@ async fn handle_pad_pressed(
 
     if state.is_delete_held {
         handle_delete_action(push2, state, address, path, coord).await?;
+    } else if state.is_capture_held {
+        handle_retro_capture(push2, state, path, coord)?;
+    } else if state.is_shift_held {
+        handle_arm_recording(push2, state, address, path, coord)?;
     } else if state.is_select_held {
         handle_select_action(push2, state, address, path, coord)?;
     } else {
-        handle_playback_or_record(push2, state, address, path, coord)?;
+        handle_playback_or_record(push2, state, address, path, coord, velocity)?;
     }
     Ok(())
 }
 
 async fn handle_delete_action(
-    push2: &mut Push2,
+    push2: &mut impl Controller,
     state: &mut AppState,
     address: u8,
     path: std::path::PathBuf,
@@ -75,16 +106,18 @@ async fn handle_delete_action(
         });
 
         // Clear state
-        state.pitch_shift_semitones.remove(&address);
-        state.playback_volume.remove(&address);
+        state.pad_settings.remove(address);
         state.waveform_cache.remove(&address);
         state.sound_data_cache.remove(&address);
+        state.render_cache.remove(&address);
+        state.renders.forget(address);
+        state.sample_durations.remove(&address);
 
-        if let Some(task) = state.auto_stop_tasks.remove(&address) {
-            task.abort();
+        if state.playing_pads.contains(&address) {
+            stop_pad(state, address)?;
         }
 
-        push2.set_pad_color(coord, COLOR_OFF)?;
+        push2.set_pad_color(coord, state.config.colors.off)?;
         if state.selected_for_edit == Some(address) {
             state.selected_for_edit = None;
         }
@@ -93,7 +126,7 @@ async fn handle_delete_action(
 }
 
 fn handle_select_action(
-    push2: &mut Push2,
+    push2: &mut impl Controller,
     state: &mut AppState,
     address: u8,
     path: std::path::PathBuf,
@@ -107,102 +140,205 @@ fn handle_select_action(
     if let Some(prev) = state.selected_for_edit {
         if prev == address {
             state.selected_for_edit = None;
-            push2.set_pad_color(coord, COLOR_HAS_FILE)?;
+            push2.set_pad_color(coord, state.config.colors.has_file)?;
             return Ok(());
         }
         // Reset old pad color
-        if let Some(old_coord) = push2.button_map.get_note(prev) {
-            push2.set_pad_color(old_coord, COLOR_HAS_FILE)?;
+        if let Some(old_coord) = push2.pad_coord(prev) {
+            push2.set_pad_color(old_coord, state.config.colors.has_file)?;
         }
     }
 
     // Select new
     state.selected_for_edit = Some(address);
-    push2.set_pad_color(coord, COLOR_SELECTED)?;
+    push2.set_pad_color(coord, state.config.colors.selected)?;
+    Ok(())
+}
+
+/// Saves the rolling input history to an empty pad. The pad shows the recording color
+/// until the file is written, then opens on the sample page for trimming.
+fn handle_retro_capture(
+    push2: &mut impl Controller,
+    state: &mut AppState,
+    path: std::path::PathBuf,
+    coord: push2::PadCoord,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if path.exists() || state.config.capture.retro_seconds <= 0.0 {
+        return Ok(());
+    }
+    info!(
+        "Saving the last {} s to {}",
+        state.config.capture.retro_seconds,
+        path.display()
+    );
+    state.audio_cmd_tx.send(AudioCommand::SaveHistory(path))?;
+    push2.set_pad_color(coord, state.config.colors.recording)?;
+    state.edit_page = EditPage::Sample;
+    state.encoder_ticks = [0; 8];
+    update_page_lights(push2, state)?;
+    Ok(())
+}
+
+/// Arms an empty pad: recording starts once the input crosses the threshold and ends by
+/// pressing the pad again or, if configured, after a stretch of silence.
+fn handle_arm_recording(
+    push2: &mut impl Controller,
+    state: &mut AppState,
+    address: u8,
+    path: std::path::PathBuf,
+    coord: push2::PadCoord,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if path.exists() || state.active_recording_key.is_some() {
+        return Ok(());
+    }
+    info!("ARM recording to {}", path.display());
+    state.audio_cmd_tx.send(AudioCommand::Arm(path))?;
+    state.active_recording_key = Some(address);
+    state.is_recording_armed = true;
+    push2.set_pad_color(coord, state.config.colors.recording)?;
     Ok(())
 }
 
 fn handle_playback_or_record(
-    push2: &mut Push2,
+    push2: &mut impl Controller,
     state: &mut AppState,
     address: u8,
     path: std::path::PathBuf,
     coord: push2::PadCoord,
+    velocity: u8,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    if state.is_recording_armed && state.active_recording_key == Some(address) {
+        info!("STOP armed recording.");
+        state.audio_cmd_tx.send(AudioCommand::Stop)?;
+        state.active_recording_key = None;
+        state.is_recording_armed = false;
+        // Still waiting for the threshold means nothing was recorded.
+        let color = if state.input_monitor.is_armed() {
+            state.config.colors.off
+        } else {
+            state.config.colors.has_file
+        };
+        push2.set_pad_color(coord, color)?;
+        return Ok(());
+    }
+
     if path.exists() {
-        push2.set_pad_color(coord, COLOR_PLAYING)?;
-        trigger_sound_playback(state, address, path)?;
+        let mode = state.pad_settings.get(address).mode;
+        if mode.is_latching() && state.playing_pads.contains(&address) {
+            stop_pad(state, address)?;
+            push2.set_pad_color(coord, resting_pad_color(state, address))?;
+            return Ok(());
+        }
+
+        push2.set_pad_color(coord, state.config.colors.playing)?;
+        trigger_sound_playback(state, address, path, velocity)?;
 
         // Auto-select on playback
         if state.selected_for_edit != Some(address) {
             // Logic to reset old selection color omitted for brevity, but follows same pattern
             state.selected_for_edit = Some(address);
         }
+    } else if state.active_recording_key.is_some() {
+        // One recording at a time, armed or running.
+        info!("Already recording; not starting {}", path.display());
     } else {
         info!("START recording to {}", path.display());
         state.audio_cmd_tx.send(AudioCommand::Start(path))?;
         state.active_recording_key = Some(address);
-        push2.set_pad_color(coord, COLOR_RECORDING)?;
+        push2.set_pad_color(coord, state.config.colors.recording)?;
     }
     Ok(())
 }
 
 fn handle_pad_released(
-    push2: &mut Push2,
+    push2: &mut impl Controller,
     state: &mut AppState,
     coord: push2::PadCoord,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let Some(address) = push2.button_map.get_note_address(coord) else {
+    let Some(address) = push2.pad_address(coord) else {
         return Ok(());
     };
 
     // Ignore release if modifiers held
-    if state.is_delete_held || state.is_select_held {
+    if state.is_delete_held || state.is_select_held || state.is_capture_held || state.is_shift_held
+    {
         // Logic to restore color if needed
         return Ok(());
     }
 
+    // Armed recordings don't need the pad held.
+    if state.is_recording_armed && state.active_recording_key == Some(address) {
+        return Ok(());
+    }
+
     if state.active_recording_key == Some(address) {
         info!("STOP recording.");
         state.audio_cmd_tx.send(AudioCommand::Stop)?;
         state.active_recording_key = None;
-        push2.set_pad_color(coord, COLOR_HAS_FILE)?;
-    } else {
-        // Playback released: reset color to Off or FilePresent
-        if let Some(path) = state.pad_files.get(&address) {
-            let color = if state.selected_for_edit == Some(address) {
-                COLOR_SELECTED
-            } else if path.exists() {
-                COLOR_HAS_FILE
-            } else {
-                COLOR_OFF
-            };
-            push2.set_pad_color(coord, color)?;
+        push2.set_pad_color(coord, state.config.colors.has_file)?;
+    } else if state.pad_files.contains_key(&address) {
+        let mode = state.pad_settings.get(address).mode;
+        if mode == PadMode::Gate && state.playing_pads.contains(&address) {
+            stop_pad(state, address)?;
+        }
+        // Latching pads stay lit until they are stopped
+        if !(mode.is_latching() && state.playing_pads.contains(&address)) {
+            push2.set_pad_color(coord, resting_pad_color(state, address))?;
         }
     }
     Ok(())
 }
 
+/// The pad's color when it is neither recording nor playing.
+fn resting_pad_color(state: &AppState, address: u8) -> u8 {
+    if state.selected_for_edit == Some(address) {
+        state.config.colors.selected
+    } else if state
+        .pad_files
+        .get(&address)
+        .is_some_and(|path| path.exists())
+    {
+        state.config.colors.has_file
+    } else {
+        state.config.colors.off
+    }
+}
+
+fn stop_pad(state: &mut AppState, address: u8) -> Result<(), Box<dyn std::error::Error>> {
+    state.playing_pads.remove(&address);
+    state.playheads.remove(&address);
+    state.kira_cmd_tx.send(KiraCommand::Stop(address))?;
+    Ok(())
+}
+
 fn handle_button_pressed(
-    push2: &mut Push2,
+    push2: &mut impl Controller,
     state: &mut AppState,
     name: ControlName,
 ) -> Result<(), Box<dyn std::error::Error>> {
     match name {
         ControlName::Delete => {
             state.is_delete_held = true;
-            push2.set_button_light(name, BUTTON_LIGHT_ON)?;
+            push2.set_button_light(name, state.config.colors.button_on)?;
         }
         ControlName::Select => {
             state.is_select_held = true;
-            push2.set_button_light(name, BUTTON_LIGHT_ON)?;
+            push2.set_button_light(name, state.config.colors.button_on)?;
+        }
+        ControlName::Capture => {
+            state.is_capture_held = true;
+            push2.set_button_light(name, state.config.colors.button_on)?;
+        }
+        ControlName::Shift => {
+            state.is_shift_held = true;
+            push2.set_button_light(name, state.config.colors.button_on)?;
         }
         ControlName::Mute => {
             state.is_mute_enabled = !state.is_mute_enabled;
             push2.set_button_light(
                 name,
                 if state.is_mute_enabled {
-                    BUTTON_LIGHT_ON
+                    state.config.colors.button_on
                 } else {
                     0
                 },
@@ -215,7 +351,7 @@ fn handle_button_pressed(
             push2.set_button_light(
                 name,
                 if state.is_solo_enabled {
-                    BUTTON_LIGHT_ON
+                    state.config.colors.button_on
                 } else {
                     0
                 },
@@ -223,23 +359,132 @@ fn handle_button_pressed(
             info!("Solo Toggled: {}", state.is_solo_enabled);
             update_audio_routing(state);
         }
+        ControlName::Clip => {
+            state.edit_page = match state.edit_page {
+                EditPage::Clip => EditPage::Sample,
+                _ => EditPage::Clip,
+            };
+            state.encoder_ticks = [0; 8];
+            update_page_lights(push2, state)?;
+        }
+        ControlName::Device => {
+            state.edit_page = match state.edit_page {
+                EditPage::Effects => EditPage::Sample,
+                _ => EditPage::Effects,
+            };
+            state.encoder_ticks = [0; 8];
+            update_page_lights(push2, state)?;
+        }
+        ControlName::Setup => {
+            state.edit_page = match state.edit_page {
+                EditPage::Setup => EditPage::Sample,
+                _ => EditPage::Setup,
+            };
+            state.encoder_ticks = [0; 8];
+            update_page_lights(push2, state)?;
+        }
+        ControlName::Undo => {
+            // Back to the full file, undoing auto-trim or any manual trim
+            if let Some(key) = state.selected_for_edit {
+                let pad = state.pad_settings.get_mut(key);
+                pad.start_point = 0.0;
+                pad.end_point = 1.0;
+                info!("Pad {} reverted to the full file", key);
+            }
+        }
+        ControlName::Convert => {
+            if let Some(key) = state.selected_for_edit {
+                let pad = state.pad_settings.get_mut(key);
+                pad.reverse = !pad.reverse;
+                info!("Pad {} reverse: {}", key, pad.reverse);
+            }
+        }
+        ControlName::Accent => {
+            state.velocity_curve = state.velocity_curve.next();
+            info!("Velocity curve: {}", state.velocity_curve.label());
+            update_accent_light(push2, state)?;
+        }
+        ControlName::PageLeft => select_bank_relative(push2, state, -1)?,
+        ControlName::PageRight => select_bank_relative(push2, state, 1)?,
+        ControlName::OctaveDown => select_bank_relative(push2, state, -BANKS_PER_OCTAVE)?,
+        ControlName::OctaveUp => select_bank_relative(push2, state, BANKS_PER_OCTAVE)?,
         _ => {}
     }
     Ok(())
 }
 
-fn update_audio_routing(state: &AppState) {
+fn select_bank_relative(
+    push2: &mut impl Controller,
+    state: &mut AppState,
+    offset: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let bank = (state.current_bank as i32 + offset).clamp(0, BANK_COUNT as i32 - 1);
+    state.switch_bank(push2, bank as u8)
+}
+
+/// Accent is lit while pad velocity affects the level of pads following the global curve.
+pub fn update_accent_light(
+    push2: &mut impl Controller,
+    state: &AppState,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let color = if state.velocity_curve == VelocityCurve::Fixed {
+        0
+    } else {
+        state.config.colors.button_on
+    };
+    push2.set_button_light(ControlName::Accent, color)?;
+    Ok(())
+}
+
+/// Lights the button of the open edit page.
+pub fn update_page_lights(
+    push2: &mut impl Controller,
+    state: &AppState,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let on = state.config.colors.button_on;
+    let light = |page: EditPage| if state.edit_page == page { on } else { 0 };
+    push2.set_button_light(ControlName::Clip, light(EditPage::Clip))?;
+    push2.set_button_light(ControlName::Device, light(EditPage::Effects))?;
+    push2.set_button_light(ControlName::Setup, light(EditPage::Setup))?;
+    Ok(())
+}
+
+fn update_reverse_light(
+    push2: &mut impl Controller,
+    state: &mut AppState,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let reversed = state
+        .selected_for_edit
+        .is_some_and(|key| state.pad_settings.get(key).reverse);
+    if reversed != state.is_reverse_light_on {
+        let color = if reversed {
+            state.config.colors.button_on
+        } else {
+            0
+        };
+        push2.set_button_light(ControlName::Convert, color)?;
+        state.is_reverse_light_on = reversed;
+    }
+    Ok(())
+}
+
+pub fn update_audio_routing(state: &AppState) {
     let current_sink = match (state.is_mute_enabled, state.is_solo_enabled) {
         (true, true) => PlaybackSink::Default,
         (false, true) => PlaybackSink::Both,
         (false, false) => PlaybackSink::Mixer,
         (true, false) => PlaybackSink::None,
     };
-    player::update_pipewire_links(current_sink);
+    if state.routing_tx.send(current_sink).is_err() {
+        error!(
+            "Routing thread is gone; cannot switch to {:?}",
+            current_sink
+        );
+    }
 }
 
 fn handle_button_released(
-    push2: &mut Push2,
+    push2: &mut impl Controller,
     state: &mut AppState,
     name: ControlName,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -252,6 +497,14 @@ fn handle_button_released(
             state.is_select_held = false;
             push2.set_button_light(name, 0)?;
         }
+        ControlName::Capture => {
+            state.is_capture_held = false;
+            push2.set_button_light(name, 0)?;
+        }
+        ControlName::Shift => {
+            state.is_shift_held = false;
+            push2.set_button_light(name, 0)?;
+        }
         _ => {}
     }
     Ok(())
@@ -268,48 +521,250 @@ fn handle_encoder_twist(
         raw_delta as i32
     };
 
+    // The master encoder works whether or not a pad is selected
+    if matches!(name, EncoderName::Master) {
+        let gain_db = state.master.gain_db() + delta as f64 * 0.5;
+        state.master.set_gain_db(gain_db);
+        return Ok(());
+    }
+
+    // The setup page edits the input, not a pad
+    if state.edit_page == EditPage::Setup {
+        return edit_setup_page(state, name, delta);
+    }
+
     // Only proceed if a pad is selected
     let Some(key) = state.selected_for_edit else {
         return Ok(());
     };
 
+    match state.edit_page {
+        EditPage::Sample => edit_sample_page(state, key, name, delta),
+        EditPage::Clip => edit_clip_page(state, key, name, delta),
+        EditPage::Effects => edit_effects_page(state, key, name, delta),
+        EditPage::Setup => Ok(()),
+    }
+}
+
+fn edit_sample_page(
+    state: &mut AppState,
+    key: u8,
+    name: EncoderName,
+    delta: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
     match name {
         EncoderName::Track1 => {
             // Volume
-            let val = state.playback_volume.entry(key).or_insert(1.0);
-            *val = (*val + delta as f64 * 0.10).clamp(-30.0, 15.0);
-            state.kira_cmd_tx.send(KiraCommand::SetVolume(key, *val))?;
+            let velocity_db = state.velocity_gain_db.get(&key).copied().unwrap_or(0.0);
+            let pad = state.pad_settings.get_mut(key);
+            pad.volume = (pad.volume + delta as f64 * 0.10).clamp(-30.0, 15.0);
+            state
+                .kira_cmd_tx
+                .send(KiraCommand::SetVolume(key, pad.volume + velocity_db))?;
         }
         EncoderName::Track2 => {
             // Pitch
-            let val = state.pitch_shift_semitones.entry(key).or_insert(0.0);
-            *val = (*val + delta as f64 * 0.1).clamp(-12.0, 12.0);
-            let rate = 2.0_f64.powf(*val / 12.0);
-            state
-                .kira_cmd_tx
-                .send(KiraCommand::SetPlaybackRate(key, rate))?;
+            let pad = state.pad_settings.get_mut(key);
+            pad.pitch_semitones = (pad.pitch_semitones + delta as f64 * 0.1).clamp(-12.0, 12.0);
+            // Stretched pads pick up the new pitch from a fresh render once edits settle
+            if pad.pitch_mode == PitchMode::Resample {
+                let rate = 2.0_f64.powf(pad.pitch_semitones / 12.0);
+                state
+                    .kira_cmd_tx
+                    .send(KiraCommand::SetPlaybackRate(key, rate))?;
+            }
         }
         EncoderName::Track3 => {
             // Start Point
-            let end = *state.sample_end_point.entry(key).or_insert(1.0);
-            let start = state.sample_start_point.entry(key).or_insert(0.0);
-            *start = (*start + delta as f64 * 0.005).clamp(0.0, end);
+            let pad = state.pad_settings.get_mut(key);
+            pad.start_point = (pad.start_point + delta as f64 * 0.005).clamp(0.0, pad.end_point);
         }
         EncoderName::Track4 => {
             // End Point
-            let start = *state.sample_start_point.entry(key).or_insert(0.0);
-            let end = state.sample_end_point.entry(key).or_insert(1.0);
-            *end = (*end + delta as f64 * 0.005).clamp(start, 1.0);
+            let pad = state.pad_settings.get_mut(key);
+            pad.end_point = (pad.end_point + delta as f64 * 0.005).clamp(pad.start_point, 1.0);
+        }
+        EncoderName::Track5 => {
+            // Playback Mode
+            let steps = discrete_steps(&mut state.encoder_ticks[4], delta);
+            if steps != 0 {
+                let pad = state.pad_settings.get_mut(key);
+                pad.mode = pad.mode.step(steps);
+                info!("Pad {} mode: {}", key, pad.mode.label());
+            }
+        }
+        EncoderName::Track6 => {
+            // Choke Group
+            let steps = discrete_steps(&mut state.encoder_ticks[5], delta);
+            if steps != 0 {
+                let pad = state.pad_settings.get_mut(key);
+                let group = pad.choke_group.unwrap_or(0) as i32 + steps;
+                pad.choke_group = match group.clamp(0, CHOKE_GROUP_COUNT as i32) {
+                    0 => None,
+                    group => Some(group as u8),
+                };
+            }
+        }
+        EncoderName::Track7 => {
+            // Velocity Curve ("global" first, then each curve)
+            let steps = discrete_steps(&mut state.encoder_ticks[6], delta);
+            if steps != 0 {
+                let pad = state.pad_settings.get_mut(key);
+                let index = pad.velocity_curve.map_or(0, |curve| curve.index() + 1) as i32;
+                pad.velocity_curve = match (index + steps).clamp(0, VelocityCurve::ALL.len() as i32)
+                {
+                    0 => None,
+                    index => Some(VelocityCurve::ALL[index as usize - 1]),
+                };
+            }
+        }
+        EncoderName::Track8 => {
+            // Voices
+            let steps = discrete_steps(&mut state.encoder_ticks[7], delta);
+            if steps != 0 {
+                let pad = state.pad_settings.get_mut(key);
+                pad.voices = (pad.voices as i32 + steps).clamp(1, MAX_VOICES as i32) as u8;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn edit_clip_page(
+    state: &mut AppState,
+    key: u8,
+    name: EncoderName,
+    delta: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match name {
+        EncoderName::Track1 => {
+            // Pitch Mode
+            let steps = discrete_steps(&mut state.encoder_ticks[0], delta);
+            if steps != 0 {
+                let pad = state.pad_settings.get_mut(key);
+                pad.pitch_mode = if steps > 0 {
+                    PitchMode::Stretch
+                } else {
+                    PitchMode::Resample
+                };
+            }
+        }
+        EncoderName::Track2 => {
+            // Tempo (stretch mode only)
+            let pad = state.pad_settings.get_mut(key);
+            pad.tempo = (pad.tempo + delta as f64 * 0.01).clamp(MIN_TEMPO, MAX_TEMPO);
+        }
+        EncoderName::Track5 => {
+            // Attack
+            let envelope = &mut state.pad_settings.get_mut(key).envelope;
+            envelope.attack_ms =
+                (envelope.attack_ms + delta as f64 * 5.0).clamp(0.0, MAX_ATTACK_MS);
+        }
+        EncoderName::Track6 => {
+            // Hold (turning below zero switches it off)
+            let envelope = &mut state.pad_settings.get_mut(key).envelope;
+            let hold_ms = envelope.hold_ms.unwrap_or(-10.0) + delta as f64 * 10.0;
+            envelope.hold_ms = (hold_ms >= 0.0).then(|| hold_ms.min(MAX_HOLD_MS));
+        }
+        EncoderName::Track7 => {
+            // Release
+            let envelope = &mut state.pad_settings.get_mut(key).envelope;
+            envelope.release_ms =
+                (envelope.release_ms + delta as f64 * 10.0).clamp(0.0, MAX_RELEASE_MS);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn edit_effects_page(
+    state: &mut AppState,
+    key: u8,
+    name: EncoderName,
+    delta: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let steps = match name {
+        EncoderName::Track1 => discrete_steps(&mut state.encoder_ticks[0], delta),
+        _ => 0,
+    };
+    let effects = &mut state.pad_settings.get_mut(key).effects;
+    match name {
+        EncoderName::Track1 => {
+            // Filter Type
+            if steps == 0 {
+                return Ok(());
+            }
+            effects.filter = effects.filter.step(steps);
+        }
+        EncoderName::Track2 => {
+            // Cutoff, an eighth of an octave per tick
+            effects.cutoff_hz = (effects.cutoff_hz * 2.0_f64.powf(delta as f64 / 8.0))
+                .clamp(MIN_CUTOFF_HZ, MAX_CUTOFF_HZ);
+        }
+        EncoderName::Track3 => {
+            // Distortion Drive
+            effects.drive_db = (effects.drive_db + delta as f64 * 0.25).clamp(0.0, MAX_DRIVE_DB);
+        }
+        EncoderName::Track4 => {
+            // Delay Mix
+            effects.delay_mix = (effects.delay_mix + delta as f64 * 0.01).clamp(0.0, 1.0);
+        }
+        EncoderName::Track5 => {
+            // Reverb Mix
+            effects.reverb_mix = (effects.reverb_mix + delta as f64 * 0.01).clamp(0.0, 1.0);
+        }
+        _ => return Ok(()),
+    }
+    let effects = *effects;
+    state
+        .kira_cmd_tx
+        .send(KiraCommand::SetEffects(key, effects))?;
+    Ok(())
+}
+
+fn edit_setup_page(
+    state: &mut AppState,
+    name: EncoderName,
+    delta: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match name {
+        EncoderName::Track1 => {
+            // Input Gain
+            state.input_gain_db = (state.input_gain_db + delta as f64 * 0.25)
+                .clamp(-MAX_INPUT_GAIN_DB, MAX_INPUT_GAIN_DB);
+            let gain = 10.0_f64.powf(state.input_gain_db / 20.0) as f32;
+            state.audio_cmd_tx.send(AudioCommand::SetInputGain(gain))?;
+        }
+        EncoderName::Track2 => {
+            // Clip Mode
+            let steps = discrete_steps(&mut state.encoder_ticks[1], delta);
+            if steps == 0 {
+                return Ok(());
+            }
+            state.input_clip_mode = state.input_clip_mode.step(steps);
+            state
+                .audio_cmd_tx
+                .send(AudioCommand::SetClipMode(state.input_clip_mode))?;
         }
         _ => {}
     }
     Ok(())
 }
 
+/// Turns encoder ticks into whole steps so list parameters don't race past their values.
+fn discrete_steps(ticks: &mut i32, delta: i32) -> i32 {
+    *ticks += delta;
+    let steps = *ticks / TICKS_PER_STEP;
+    *ticks %= TICKS_PER_STEP;
+    steps
+}
+
 fn trigger_sound_playback(
     state: &mut AppState,
     address: u8,
     path: std::path::PathBuf,
+    velocity: u8,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // 1. Load Data
     let sound_data = if let Some(data) = state.sound_data_cache.get(&address) {
@@ -328,59 +783,152 @@ fn trigger_sound_playback(
     };
 
     // 2. Params
-    let pitch = *state.pitch_shift_semitones.get(&address).unwrap_or(&0.0);
-    let volume = *state.playback_volume.get(&address).unwrap_or(&1.0);
-    let start_pct = *state.sample_start_point.get(&address).unwrap_or(&0.0);
-    let end_pct = *state.sample_end_point.get(&address).unwrap_or(&1.0);
+    let pad = state.pad_settings.get(address);
+    let pitch = pad.pitch_semitones;
+    let velocity_db = state
+        .velocity_curve_for(address)
+        .gain_db(velocity, &state.config.velocity.table);
+    state.velocity_gain_db.insert(address, velocity_db);
+    let volume = pad.volume + velocity_db;
+    let start_pct = pad.start_point;
+    let end_pct = pad.end_point;
 
     let rate = 2.0_f64.powf(pitch / 12.0);
     let dur = sound_data.duration().as_secs_f64();
     let start_sec = dur * start_pct;
+    let end_sec = dur * end_pct;
+    if end_sec - start_sec < MIN_TRIM_SECONDS {
+        info!("Trim range of pad {} is empty; nothing to play", address);
+        return Ok(());
+    }
 
-    let settings = StaticSoundSettings::new()
+    // 3. Trim: the engine only sees the audio between the trim points, so the end is
+    // sample-accurate whatever the playback rate does while it plays.
+    let stretching = pad.pitch_mode == PitchMode::Stretch;
+    let (sound_data, rate) = match render_params(&pad, dur) {
+        // Stretching and fades are baked into an offline render of the trimmed region.
+        Some(params) => match cached_render(state, address, &params) {
+            Some(rendered) => (rendered, if stretching { 1.0 } else { rate }),
+            // The plain slice would tie the tempo to the pitch again, so the hit is dropped
+            // and the pad shows as rendering until it can play.
+            None if stretching => {
+                info!("Pad {} is still rendering", address);
+                state.renders.request(address, params, time::Duration::ZERO);
+                return Ok(());
+            }
+            // Fades alone take a single pass over the region, so they are rendered here
+            // and the first hit after an edit is already shaped.
+            None => {
+                let rendered = render::render(&sound_data, &params);
+                state
+                    .render_cache
+                    .insert(address, (params, rendered.clone()));
+                (rendered, rate)
+            }
+        },
+        None => (sound_data.slice(start_sec..end_sec), rate),
+    };
+    let mut settings = StaticSoundSettings::new()
         .volume(volume as f32)
         .playback_rate(rate)
-        .start_position(start_sec);
+        .reverse(pad.reverse);
+    if pad.mode == PadMode::Loop {
+        settings = settings.loop_region(..);
+    }
 
+    let generation = state.play_generations.entry(address).or_default();
+    *generation += 1;
     state
         .kira_cmd_tx
         .send(KiraCommand::Play(player::KiraPlayRequest {
-
             pad_key: address,
             sound_data,
             settings,
+            choke_group: pad.choke_group,
+            voices: pad.voices,
+            effects: pad.effects,
+            generation: *generation,
         }))?;
+    state.playing_pads.insert(address);
+    Ok(())
+}
 
-    // 4. Auto-Stop Task
-    let end_sec = dur * end_pct;
-    let play_dur = (end_sec - start_sec).max(0.0);
-    let real_dur = if rate.abs() > 0.001 {
-        play_dur / rate.abs()
-    } else {
-        0.0
-    };
+/// What the pad's offline render depends on, or `None` if it plays straight from the file.
+fn render_params(pad: &PadSettings, duration: f64) -> Option<RenderParams> {
+    let stretching = pad.pitch_mode == PitchMode::Stretch;
+    if !stretching && pad.envelope.is_flat() {
+        return None;
+    }
+    Some(RenderParams {
+        stretch: StretchParams {
+            start_sec: duration * pad.start_point,
+            end_sec: duration * pad.end_point,
+            semitones: if stretching { pad.pitch_semitones } else { 0.0 },
+            tempo: if stretching { pad.tempo } else { 1.0 },
+        },
+        envelope: pad.envelope,
+        reverse: pad.reverse,
+    })
+}
 
-    if let Some(old) = state.auto_stop_tasks.remove(&address) {
-        old.abort();
+/// The pad's cached render, if it was made with `params`.
+fn cached_render(state: &AppState, address: u8, params: &RenderParams) -> Option<StaticSoundData> {
+    match state.render_cache.get(&address) {
+        Some((cached_params, rendered)) if cached_params == params => Some(rendered.clone()),
+        _ => None,
     }
+}
 
-    let kira_tx = state.kira_cmd_tx.clone();
-    let task = tokio::spawn(async move {
-        tokio::time::sleep(time::Duration::from_secs_f64(real_dur)).await;
-        let _ = kira_tx.send(KiraCommand::Stop(address));
-    });
-    state.auto_stop_tasks.insert(address, task);
+/// Asks for a fresh render of the selected pad, if it is stretched, when its edits have
+/// changed what the cached one was made from, and starts the renders that are due on worker
+/// threads.
+fn schedule_renders(state: &mut AppState) {
+    if let Some(address) = state.selected_for_edit {
+        let pad = state.pad_settings.get(address);
+        // Pads that haven't been loaded yet are rendered on their first hit.
+        let duration = state
+            .sound_data_cache
+            .get(&address)
+            .map(|data| data.duration().as_secs_f64());
+        let stale = duration
+            .filter(|_| pad.pitch_mode == PitchMode::Stretch)
+            .and_then(|duration| render_params(&pad, duration))
+            .filter(|params| cached_render(state, address, params).is_none());
+        if let Some(params) = stale {
+            state.renders.request(address, params, RENDER_SETTLE_DELAY);
+        }
+    }
+    for (address, job, params) in state.renders.take_due() {
+        match state.sound_data_cache.get(&address) {
+            Some(data) => render::spawn(address, job, params, data.clone(), state.app_tx.clone()),
+            None => state.renders.forget(address),
+        }
+    }
+}
 
-    Ok(())
+/// Moves a new recording's start and end points past the silence around its audio.
+fn auto_trim(state: &mut AppState, address: u8, trim: Option<TrimPoints>) {
+    match trim {
+        Some(points) => {
+            let pad = state.pad_settings.get_mut(address);
+            pad.start_point = points.start;
+            pad.end_point = points.end;
+            info!(
+                "Pad {} trimmed to {:.3}-{:.3}",
+                address, points.start, points.end
+            );
+        }
+        None => info!("Pad {} is silent, leaving it untrimmed", address),
+    }
 }
 
 fn handle_app_command(
-    push2: &mut Push2,
+    push2: &mut impl Controller,
     state: &mut AppState,
     cmd: AppCommand,
 ) -> Result<(), Box<dyn std::error::Error>> {
     match cmd {
-        AppCommand::FileSaved(path) => {
+        AppCommand::FileSaved(path, trim) => {
             info!("File saved: {}", path.display());
             // Clear Caches
             let mut target_addr = None;
@@ -391,15 +939,74 @@ fn handle_app_command(
                 }
             }
             if let Some(addr) = target_addr {
+                // Still recording here means it hit the length limit or, armed, fell silent.
+                if state.active_recording_key == Some(addr) {
+                    info!("Recording stopped on its own");
+                    state.active_recording_key = None;
+                    state.is_recording_armed = false;
+                }
                 state.waveform_cache.remove(&addr);
                 state.sound_data_cache.remove(&addr);
+                state.render_cache.remove(&addr);
+                state.renders.forget(addr);
+                state.sample_durations.remove(&addr);
+                if state.config.capture.auto_trim {
+                    auto_trim(state, addr, trim);
+                }
                 // Update Selection to new file
                 state.selected_for_edit = Some(addr);
-                if let Some(coord) = push2.button_map.get_note(addr) {
-                    push2.set_pad_color(coord, COLOR_SELECTED)?;
+                if let Some(coord) = push2.pad_coord(addr) {
+                    push2.set_pad_color(coord, state.config.colors.selected)?;
+                }
+            }
+        }
+        AppCommand::RecordingFailed(path) => {
+            error!("Nothing was recorded to {}", path.display());
+            let target_addr = state
+                .pad_files
+                .iter()
+                .find(|(_, p)| **p == path)
+                .map(|(addr, _)| *addr);
+            if let Some(addr) = target_addr {
+                if state.active_recording_key == Some(addr) {
+                    state.active_recording_key = None;
+                    state.is_recording_armed = false;
+                }
+                if let Some(coord) = push2.pad_coord(addr) {
+                    push2.set_pad_color(coord, resting_pad_color(state, addr))?;
+                }
+            }
+        }
+        AppCommand::Rendered(rendered) => {
+            // Renders overtaken by later edits, a new recording or a bank switch are dropped.
+            if state.renders.finish(rendered.address, rendered.job) {
+                state
+                    .render_cache
+                    .insert(rendered.address, (rendered.params, rendered.sound));
+            }
+        }
+        AppCommand::RoutingStatus(status) => {
+            state.routing_status = Some(status);
+        }
+        AppCommand::Playhead(address, played) => {
+            // A late report can arrive after the pad was stopped from here.
+            if state.playing_pads.contains(&address) {
+                state.playheads.insert(address, played);
+            }
+        }
+        AppCommand::PlaybackFinished(address, generation) => {
+            // The pad was retriggered after this play stopped; the new play is still going.
+            if state.play_generations.get(&address) != Some(&generation) {
+                return Ok(());
+            }
+            state.playing_pads.remove(&address);
+            state.playheads.remove(&address);
+            if state.active_recording_key != Some(address) {
+                if let Some(coord) = push2.pad_coord(address) {
+                    push2.set_pad_color(coord, resting_pad_color(state, address))?;
                 }
             }
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
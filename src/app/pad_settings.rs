@@ -0,0 +1,209 @@
+use crate::app::velocity::VelocityCurve;
+use crate::audio::effects::PadEffects;
+use crate::audio::envelope::Envelope;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub const SETTINGS_FILE_NAME: &str = "pads.json";
+// Encoder edits arrive in bursts; wait for the knob to rest before touching the disk.
+const SAVE_SETTLE_DELAY: Duration = Duration::from_millis(750);
+pub const CHOKE_GROUP_COUNT: u8 = 8;
+pub const MAX_VOICES: u8 = 8;
+pub const MIN_TEMPO: f64 = 0.5;
+pub const MAX_TEMPO: f64 = 2.0;
+
+/// What a pad does when it is pressed and released.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PadMode {
+    /// Plays from the start to the end trim point.
+    #[default]
+    OneShot,
+    /// Plays while the pad is held.
+    Gate,
+    /// A second press stops playback.
+    Toggle,
+    /// Repeats between the trim points until pressed again.
+    Loop,
+}
+
+impl PadMode {
+    pub const ALL: [PadMode; 4] = [
+        PadMode::OneShot,
+        PadMode::Gate,
+        PadMode::Toggle,
+        PadMode::Loop,
+    ];
+
+    pub fn index(self) -> usize {
+        Self::ALL.iter().position(|&mode| mode == self).unwrap_or(0)
+    }
+
+    /// Moves `steps` entries through `ALL`, stopping at either end.
+    pub fn step(self, steps: i32) -> Self {
+        let index = (self.index() as i32 + steps).clamp(0, Self::ALL.len() as i32 - 1);
+        Self::ALL[index as usize]
+    }
+
+    /// Whether the pad keeps playing after release until it is pressed again.
+    pub fn is_latching(self) -> bool {
+        matches!(self, PadMode::Toggle | PadMode::Loop)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PadMode::OneShot => "ONE SHOT",
+            PadMode::Gate => "GATE",
+            PadMode::Toggle => "TOGGLE",
+            PadMode::Loop => "LOOP",
+        }
+    }
+}
+
+/// How pitch is changed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PitchMode {
+    /// Changes the playback rate, so higher pitch also means shorter.
+    #[default]
+    Resample,
+    /// Renders a time-stretched copy, so pitch and tempo change independently.
+    Stretch,
+}
+
+impl PitchMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            PitchMode::Resample => "RESAMPLE",
+            PitchMode::Stretch => "STRETCH",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct PadSettings {
+    pub volume: f64,
+    pub pitch_semitones: f64,
+    pub start_point: f64,
+    pub end_point: f64,
+    pub mode: PadMode,
+    /// Pads sharing a group cut each other off. `None` plays freely.
+    pub choke_group: Option<u8>,
+    /// Overrides the global velocity curve. `None` follows it.
+    pub velocity_curve: Option<VelocityCurve>,
+    /// Overlapping retriggers allowed before the oldest voice is cut.
+    pub voices: u8,
+    /// Plays the trimmed region from the end point back to the start point.
+    pub reverse: bool,
+    pub pitch_mode: PitchMode,
+    /// Speed factor used in stretch mode, independent of pitch.
+    pub tempo: f64,
+    /// Fades applied to the trimmed region so mid-waveform trims don't click.
+    pub envelope: Envelope,
+    pub effects: PadEffects,
+}
+
+impl Default for PadSettings {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            pitch_semitones: 0.0,
+            start_point: 0.0,
+            end_point: 1.0,
+            mode: PadMode::OneShot,
+            choke_group: None,
+            velocity_curve: None,
+            voices: 1,
+            reverse: false,
+            pitch_mode: PitchMode::Resample,
+            tempo: 1.0,
+            envelope: Envelope::default(),
+            effects: PadEffects::default(),
+        }
+    }
+}
+
+pub struct PadSettingsStore {
+    path: PathBuf,
+    pads: HashMap<u8, PadSettings>,
+    pending_since: Option<Instant>,
+}
+
+impl PadSettingsStore {
+    /// Loads the store from `dir`. A missing or unreadable file yields an empty store.
+    pub fn load(dir: &Path) -> Self {
+        let path = dir.join(SETTINGS_FILE_NAME);
+        let pads = match fs::read_to_string(&path) {
+            Ok(contents) => match serde_json::from_str(&contents) {
+                Ok(pads) => pads,
+                Err(e) => {
+                    warn!("Ignoring corrupt pad settings {}: {}", path.display(), e);
+                    HashMap::new()
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                warn!("Could not read pad settings {}: {}", path.display(), e);
+                HashMap::new()
+            }
+        };
+        info!("Loaded settings for {} pads", pads.len());
+        Self {
+            path,
+            pads,
+            pending_since: None,
+        }
+    }
+
+    pub fn get(&self, key: u8) -> PadSettings {
+        self.pads.get(&key).copied().unwrap_or_default()
+    }
+
+    /// Returns the pad's settings for editing and schedules a save once edits settle.
+    pub fn get_mut(&mut self, key: u8) -> &mut PadSettings {
+        self.pending_since = Some(Instant::now());
+        self.pads.entry(key).or_default()
+    }
+
+    pub fn remove(&mut self, key: u8) {
+        if self.pads.remove(&key).is_some() {
+            self.pending_since = None;
+            self.save_or_log();
+        }
+    }
+
+    pub fn save_if_settled(&mut self) {
+        if let Some(since) = self.pending_since {
+            if since.elapsed() >= SAVE_SETTLE_DELAY {
+                self.pending_since = None;
+                self.save_or_log();
+            }
+        }
+    }
+
+    /// Writes any edits that are still waiting for the settle delay.
+    pub fn flush(&mut self) {
+        if self.pending_since.take().is_some() {
+            self.save_or_log();
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(&self.pads).map_err(std::io::Error::other)?;
+        // Write to a sibling file first so a crash never leaves a truncated store behind.
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, json)?;
+        fs::rename(&tmp_path, &self.path)
+    }
+
+    fn save_or_log(&self) {
+        if let Err(e) = self.save() {
+            error!("Failed to save pad settings: {}", e);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use crate::app::state::{AppCommand, RenderParams};
+use crate::audio::stretch;
+use kira::sound::static_sound::StaticSoundData;
+use log::info;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+/// How long a pad's parameters must stay put before it is rendered again, so turning an
+/// encoder doesn't start a render on every tick.
+pub const RENDER_SETTLE_DELAY: Duration = Duration::from_millis(250);
+
+/// A render made on a worker thread, on its way back to the event loop.
+#[derive(Debug)]
+pub struct RenderedPad {
+    pub address: u8,
+    pub job: u64,
+    pub params: RenderParams,
+    pub sound: StaticSoundData,
+}
+
+/// The render a pad is waiting for.
+struct PendingRender {
+    params: RenderParams,
+    due: Instant,
+    /// Set once the render has been started.
+    job: Option<u64>,
+}
+
+/// Which pads need an offline render and which renders are running. Only the newest
+/// render asked for a pad is kept when it comes back.
+#[derive(Default)]
+pub struct RenderQueue {
+    pads: HashMap<u8, PendingRender>,
+    next_job: u64,
+}
+
+impl RenderQueue {
+    /// Asks for the pad to be rendered with `params` once `delay` has passed. Other
+    /// parameters replace the earlier request, along with any render already running for it.
+    pub fn request(&mut self, address: u8, params: RenderParams, delay: Duration) {
+        let due = Instant::now() + delay;
+        match self.pads.get_mut(&address) {
+            Some(pending) if pending.params == params => pending.due = pending.due.min(due),
+            _ => {
+                let pending = PendingRender {
+                    params,
+                    due,
+                    job: None,
+                };
+                self.pads.insert(address, pending);
+            }
+        }
+    }
+
+    /// Requests that are due and not running yet, each marked as started under a new job.
+    pub fn take_due(&mut self) -> Vec<(u8, u64, RenderParams)> {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        for (&address, pending) in &mut self.pads {
+            if pending.job.is_none() && pending.due <= now {
+                self.next_job += 1;
+                pending.job = Some(self.next_job);
+                due.push((address, self.next_job, pending.params));
+            }
+        }
+        due
+    }
+
+    /// Whether `job` is still the render the pad is waiting for, which completes the request.
+    pub fn finish(&mut self, address: u8, job: u64) -> bool {
+        let current = self
+            .pads
+            .get(&address)
+            .is_some_and(|pending| pending.job == Some(job));
+        if current {
+            self.pads.remove(&address);
+        }
+        current
+    }
+
+    /// Whether the pad is waiting for a render, started or not.
+    pub fn is_pending(&self, address: u8) -> bool {
+        self.pads.contains_key(&address)
+    }
+
+    /// Drops the pad's request; a render still running for it is ignored when it returns.
+    pub fn forget(&mut self, address: u8) {
+        self.pads.remove(&address);
+    }
+
+    pub fn clear(&mut self) {
+        self.pads.clear();
+    }
+}
+
+/// Renders the pad on a blocking worker and posts it back as `AppCommand::Rendered`.
+pub fn spawn(
+    address: u8,
+    job: u64,
+    params: RenderParams,
+    sound_data: StaticSoundData,
+    app_tx: Sender<AppCommand>,
+) {
+    tokio::task::spawn_blocking(move || {
+        let started = Instant::now();
+        let sound = render(&sound_data, &params);
+        info!("Rendered pad {} in {:?}", address, started.elapsed());
+        let _ = app_tx.send(AppCommand::Rendered(RenderedPad {
+            address,
+            job,
+            params,
+            sound,
+        }));
+    });
+}
+
+/// Stretches and fades the trimmed region of `data` as `params` describe.
+pub fn render(data: &StaticSoundData, params: &RenderParams) -> StaticSoundData {
+    let mut frames = stretch::render_frames(data, &params.stretch);
+    params
+        .envelope
+        .apply(&mut frames, data.sample_rate, params.reverse);
+    StaticSoundData {
+        sample_rate: data.sample_rate,
+        frames: Arc::from(frames),
+        settings: Default::default(),
+        slice: None,
+    }
+}
@@ -1,92 +1,223 @@
+use crate::app::pad_settings::{PadSettingsStore, SETTINGS_FILE_NAME};
+use crate::app::render::{RenderQueue, RenderedPad};
+use crate::app::velocity::VelocityCurve;
+use crate::audio::capture::monitor::InputMonitor;
+use crate::audio::capture::{self, gain::InputClipMode};
+use crate::audio::envelope::Envelope;
+use crate::audio::master::MasterBus;
+use crate::audio::meter::Meters;
 use crate::audio::player::KiraCommand;
+use crate::audio::routing::{PlaybackSink, RoutingStatus};
+use crate::audio::stretch::StretchParams;
+use crate::audio::trim::TrimPoints;
+use crate::config::Config;
+use crate::hardware::controller::Controller;
 use kira::sound::static_sound::StaticSoundData;
 use log::info;
-use push2::{PadCoord, Push2, Push2Colors};
+use push2::{ControlName, PadCoord};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::sync::mpsc;
-use tokio::task::JoinHandle;
-
-pub const COLOR_OFF: u8 = Push2Colors::BLACK;
-pub const COLOR_HAS_FILE: u8 = Push2Colors::BLUE_SKY;
-pub const COLOR_RECORDING: u8 = Push2Colors::RED;
-pub const COLOR_PLAYING: u8 = Push2Colors::PINK;
-pub const COLOR_SELECTED: u8 = Push2Colors::PURPLE;
-pub const BUTTON_LIGHT_ON: u8 = Push2Colors::GREEN_PALE;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, mpsc};
+
+pub const BANK_COUNT: u8 = 16;
+
+/// Which set of parameters the eight encoders edit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EditPage {
+    /// Level, pitch, trim and how the pad responds to presses.
+    #[default]
+    Sample,
+    /// How the clip is shaped: stretching and the amplitude envelope.
+    Clip,
+    /// Filter, distortion, delay and reverb.
+    Effects,
+    /// Input gain and clipping; not tied to a pad.
+    Setup,
+}
+
+impl EditPage {
+    pub fn label(self) -> &'static str {
+        match self {
+            EditPage::Sample => "SAMPLE",
+            EditPage::Clip => "CLIP",
+            EditPage::Effects => "EFFECTS",
+            EditPage::Setup => "SETUP",
+        }
+    }
+}
+
+/// What an offline render of a pad depends on; a cached render is reused while these match.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderParams {
+    pub stretch: StretchParams,
+    pub envelope: Envelope,
+    pub reverse: bool,
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum AudioCommand {
     Start(PathBuf),
+    /// Starts recording to the path once the input crosses `capture.arm_threshold_db`.
+    Arm(PathBuf),
     Stop,
+    /// Saves the last `capture.retro_seconds` of input to the path.
+    SaveHistory(PathBuf),
+    /// Linear gain applied to captured audio.
+    SetInputGain(f32),
+    SetClipMode(InputClipMode),
 }
 
 #[derive(Debug)]
 pub enum AppCommand {
-    FileSaved(PathBuf),
+    /// A recording or save reached its path, with where its audio begins and ends.
+    FileSaved(PathBuf, Option<TrimPoints>),
+    /// Recording or saving to the path couldn't start, e.g. because its file couldn't be
+    /// created.
+    RecordingFailed(PathBuf),
+    RoutingStatus(RoutingStatus),
+    /// Every voice of the pad stopped, with the generation of the pad's last play.
+    PlaybackFinished(u8, u64),
+    /// Seconds played of the pad's newest voice, in its playback direction.
+    Playhead(u8, f64),
+    /// An offline render of a pad finished on a worker thread.
+    Rendered(RenderedPad),
 }
 
 pub struct AppState {
+    pub config: Config,
+    pub current_bank: u8,
+    /// Velocity curve for pads without their own; starts from the config.
+    pub velocity_curve: VelocityCurve,
+    /// Gain offset from the velocity of each pad's last hit, reapplied on volume edits.
+    pub velocity_gain_db: HashMap<u8, f64>,
     pub pad_files: HashMap<u8, PathBuf>,
     pub is_mute_enabled: bool,
     pub is_solo_enabled: bool,
-    pub playback_volume: HashMap<u8, f64>,
-    pub pitch_shift_semitones: HashMap<u8, f64>,
+    pub pad_settings: PadSettingsStore,
     pub active_recording_key: Option<u8>,
+    /// The active recording was armed with Shift: it waits for the input threshold and
+    /// ends by pressing the pad again rather than on release.
+    pub is_recording_armed: bool,
     pub selected_for_edit: Option<u8>,
     pub audio_cmd_tx: mpsc::Sender<AudioCommand>,
     pub is_delete_held: bool,
     pub is_select_held: bool,
+    pub is_capture_held: bool,
+    pub is_shift_held: bool,
     pub waveform_cache: HashMap<u8, Option<Vec<(f32, f32)>>>,
-    pub sample_start_point: HashMap<u8, f64>,
-    pub sample_end_point: HashMap<u8, f64>,
     pub kira_cmd_tx: mpsc::Sender<KiraCommand>,
+    /// Lets background work, such as renders, report back to the event loop.
+    pub app_tx: mpsc::Sender<AppCommand>,
+    pub routing_tx: pipewire::channel::Sender<PlaybackSink>,
+    pub routing_status: Option<RoutingStatus>,
     pub sound_data_cache: HashMap<u8, StaticSoundData>,
-    pub auto_stop_tasks: HashMap<u8, JoinHandle<()>>,
+    /// Stretched or enveloped renders of pads, with the parameters they were made for.
+    pub render_cache: HashMap<u8, (RenderParams, StaticSoundData)>,
+    /// Renders waiting for edits to settle or running in the background.
+    pub renders: RenderQueue,
+    /// Length in seconds of each pad's sample, read alongside its waveform.
+    pub sample_durations: HashMap<u8, f64>,
+    pub edit_page: EditPage,
+    pub playing_pads: HashSet<u8>,
+    /// Generation of each pad's latest play request. Kept across banks, so reports about
+    /// plays from before a retrigger or a bank switch are recognised as stale.
+    pub play_generations: HashMap<u8, u64>,
+    /// Latest reported position of each playing pad, as seconds played of its sound.
+    pub playheads: HashMap<u8, f64>,
+    /// Encoder ticks not yet turned into a step of a discrete parameter, per encoder.
+    pub encoder_ticks: [i32; 8],
+    /// Whether the reverse button LED is lit, so it is only resent on change.
+    pub is_reverse_light_on: bool,
     pub audio_storage_path: PathBuf,
+    /// Master gain and limiter readout, shared with the playback thread.
+    pub master: Arc<MasterBus>,
+    /// Output levels of the master bus and each pad, written by the playback thread.
+    pub meters: Arc<Meters>,
+    /// Input level and recording progress, written by the capture thread.
+    pub input_monitor: Arc<InputMonitor>,
+    /// Capture gain in dB and clip mode, starting from the config and edited on the setup page.
+    pub input_gain_db: f64,
+    pub input_clip_mode: InputClipMode,
 }
 
 impl AppState {
     pub fn new(
+        config: Config,
         audio_cmd_tx: mpsc::Sender<AudioCommand>,
         kira_cmd_tx: mpsc::Sender<KiraCommand>,
-    ) -> std::io::Result<Self> {
-        let audio_storage_path = get_audio_storage_path()?;
+        app_tx: mpsc::Sender<AppCommand>,
+        routing_tx: pipewire::channel::Sender<PlaybackSink>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let audio_storage_path = get_audio_storage_path(&config)?;
         info!("Audio storage path: {}", audio_storage_path.display());
+        migrate_flat_layout(&audio_storage_path)?;
+        // Nothing is recording yet, so any part file is left over from a crash.
+        for bank in 0..BANK_COUNT {
+            capture::recover_parts(&bank_dir(&audio_storage_path, bank));
+        }
+        let bank_path = bank_dir(&audio_storage_path, 0);
+        fs::create_dir_all(&bank_path)?;
+        let pad_settings = PadSettingsStore::load(&bank_path);
+        let velocity_curve = config.velocity.curve;
+        let input_gain_db = 20.0 * (config.capture.gain as f64).log10();
+        let input_clip_mode = config.capture.clip_mode;
         Ok(Self {
+            config,
+            current_bank: 0,
+            velocity_curve,
+            velocity_gain_db: HashMap::new(),
             pad_files: HashMap::new(),
             is_mute_enabled: true,
             is_solo_enabled: true,
-            playback_volume: HashMap::new(),
-            pitch_shift_semitones: HashMap::new(),
+            pad_settings,
             active_recording_key: None,
+            is_recording_armed: false,
             selected_for_edit: None,
             audio_cmd_tx,
             is_delete_held: false,
             is_select_held: false,
+            is_capture_held: false,
+            is_shift_held: false,
             waveform_cache: HashMap::new(),
-            sample_start_point: HashMap::new(),
-            sample_end_point: HashMap::new(),
             kira_cmd_tx,
+            app_tx,
+            routing_tx,
+            routing_status: None,
             sound_data_cache: HashMap::new(),
-            auto_stop_tasks: HashMap::new(),
+            render_cache: HashMap::new(),
+            renders: RenderQueue::default(),
+            sample_durations: HashMap::new(),
+            edit_page: EditPage::Sample,
+            playing_pads: HashSet::new(),
+            play_generations: HashMap::new(),
+            playheads: HashMap::new(),
+            encoder_ticks: [0; 8],
+            is_reverse_light_on: false,
             audio_storage_path,
+            master: Arc::new(MasterBus::default()),
+            meters: Arc::new(Meters::default()),
+            input_monitor: Arc::new(InputMonitor::default()),
+            input_gain_db,
+            input_clip_mode,
         })
     }
 
     pub fn update_pad_lights(
         &mut self,
-        push2: &mut Push2,
+        push2: &mut impl Controller,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        let bank_path = self.bank_path();
         for y in 0..8 {
             for x in 0..8 {
                 let coord = PadCoord { x, y };
-                let mut color = COLOR_OFF;
-                if let Some(address) = push2.button_map.get_note_address(coord) {
+                let mut color = self.config.colors.off;
+                if let Some(address) = push2.pad_address(coord) {
                     let file_name = format!("pad_{}_{}.wav", x, y);
-                    let file_path = self.audio_storage_path.join(file_name);
+                    let file_path = bank_path.join(file_name);
                     if file_path.exists() {
-                        color = COLOR_HAS_FILE;
+                        color = self.config.colors.has_file;
                     }
                     self.pad_files.insert(address, file_path);
                 }
@@ -95,15 +226,102 @@ impl AppState {
         }
         Ok(())
     }
+
+    pub fn velocity_curve_for(&self, key: u8) -> VelocityCurve {
+        self.pad_settings
+            .get(key)
+            .velocity_curve
+            .unwrap_or(self.velocity_curve)
+    }
+
+    pub fn bank_path(&self) -> PathBuf {
+        bank_dir(&self.audio_storage_path, self.current_bank)
+    }
+
+    /// Makes `bank` the active bank: loads its settings and redraws the pads.
+    /// Ignored while a recording is in progress, since its path belongs to the old bank.
+    pub fn switch_bank(
+        &mut self,
+        push2: &mut impl Controller,
+        bank: u8,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if bank == self.current_bank || bank >= BANK_COUNT {
+            return Ok(());
+        }
+        if self.active_recording_key.is_some() {
+            info!("Finish recording before switching banks");
+            return Ok(());
+        }
+
+        // The player knows pads only by address, which the new bank's pads reuse, so
+        // nothing from the old bank may keep playing.
+        self.kira_cmd_tx.send(KiraCommand::StopAll)?;
+        self.playing_pads.clear();
+        self.playheads.clear();
+        self.pad_settings.flush();
+        self.current_bank = bank;
+        let bank_path = self.bank_path();
+        fs::create_dir_all(&bank_path)?;
+        self.pad_settings = PadSettingsStore::load(&bank_path);
+        self.waveform_cache.clear();
+        self.sound_data_cache.clear();
+        self.render_cache.clear();
+        self.renders.clear();
+        self.sample_durations.clear();
+        self.velocity_gain_db.clear();
+        self.selected_for_edit = None;
+        info!("Switched to bank {}", bank + 1);
+
+        self.update_pad_lights(push2)?;
+        self.update_bank_lights(push2)
+    }
+
+    /// Lights the page/octave buttons that lead to another bank.
+    pub fn update_bank_lights(
+        &self,
+        push2: &mut impl Controller,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let on = self.config.colors.button_on;
+        let light = |available: bool| if available { on } else { 0 };
+        let bank = self.current_bank;
+        push2.set_button_light(ControlName::PageLeft, light(bank > 0))?;
+        push2.set_button_light(ControlName::PageRight, light(bank + 1 < BANK_COUNT))?;
+        push2.set_button_light(ControlName::OctaveDown, light(bank > 0))?;
+        push2.set_button_light(ControlName::OctaveUp, light(bank + 1 < BANK_COUNT))?;
+        Ok(())
+    }
+}
+
+/// Directory holding the samples and `pads.json` of a zero-based bank.
+pub fn bank_dir(root: &Path, bank: u8) -> PathBuf {
+    root.join(format!("bank_{:02}", bank + 1))
 }
 
-pub fn get_audio_storage_path() -> std::io::Result<PathBuf> {
-    match dirs::audio_dir() {
-        Some(mut path) => {
-            path.push("soundboard-recordings");
-            std::fs::create_dir_all(&path)?;
-            Ok(path)
+/// Moves samples and settings saved before banks existed into the first bank.
+fn migrate_flat_layout(root: &Path) -> std::io::Result<()> {
+    let first_bank = bank_dir(root, 0);
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let is_pad_file = name.starts_with("pad_") && name.ends_with(".wav");
+        if !path.is_file() || !(is_pad_file || name == SETTINGS_FILE_NAME) {
+            continue;
         }
-        None => Err(std::io::Error::other("Could not find audio directory")),
+        let target = first_bank.join(name);
+        if target.exists() {
+            continue;
+        }
+        fs::create_dir_all(&first_bank)?;
+        fs::rename(&path, &target)?;
+        info!("Moved {} into {}", name, first_bank.display());
     }
+    Ok(())
+}
+
+pub fn get_audio_storage_path(config: &Config) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let path = config.storage_dir()?;
+    std::fs::create_dir_all(&path)?;
+    Ok(path)
 }
@@ -1,11 +1,23 @@
-use crate::app::state::AppState;
+use crate::app::pad_settings::{
+    CHOKE_GROUP_COUNT, MAX_TEMPO, MAX_VOICES, MIN_TEMPO, PadMode, PadSettings, PitchMode,
+};
+use crate::app::state::{AppState, EditPage};
+use crate::app::velocity::VelocityCurve;
+use crate::audio::capture::gain::{InputClipMode, MAX_INPUT_GAIN_DB};
+use crate::audio::capture::monitor::InputMonitor;
+use crate::audio::effects::{FilterKind, MAX_CUTOFF_HZ, MAX_DRIVE_DB, MIN_CUTOFF_HZ};
+use crate::audio::envelope::{Envelope, MAX_ATTACK_MS, MAX_HOLD_MS, MAX_RELEASE_MS};
+use crate::audio::meter::LevelMeter;
+use crate::audio::routing::RoutingStatus;
+use crate::hardware::controller::Controller;
 use embedded_graphics::{
+    mono_font::{MonoTextStyle, ascii::FONT_10X20},
     pixelcolor::Bgr565,
     prelude::*,
-    primitives::{Line, Primitive, PrimitiveStyle},
+    primitives::{Line, Polyline, Primitive, PrimitiveStyle, Rectangle, Triangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
 };
 use log::warn;
-use push2::{GuiApi, Push2};
 
 // Constants moved here
 const WAVEFORM_Y_START: i32 = 0;
@@ -16,27 +28,81 @@ const WAVEFORM_WIDTH: i32 = WAVEFORM_X_END - WAVEFORM_X_START;
 const COLOR_WAVEFORM: Bgr565 = Bgr565::CYAN;
 const COLOR_START_LINE: Bgr565 = Bgr565::GREEN;
 const COLOR_STOP_LINE: Bgr565 = Bgr565::RED;
+const COLOR_PLAYHEAD: Bgr565 = Bgr565::YELLOW;
+const COLOR_DIRECTION_ARROW: Bgr565 = Bgr565::WHITE;
+const DIRECTION_ARROW_SIZE: i32 = 10;
+const COLOR_ENVELOPE: Bgr565 = Bgr565::new(31, 63, 15);
+// The envelope overlay spans this band, full level at the top.
+const ENVELOPE_Y_TOP: i32 = 30;
+const ENVELOPE_Y_BOTTOM: i32 = 130;
 const COLOR_ENCODER_OUTLINE: Bgr565 = Bgr565::WHITE;
 const COLOR_VOLUME_BAR: Bgr565 = Bgr565::GREEN;
 const COLOR_PITCH_BAR: Bgr565 = Bgr565::MAGENTA;
+const COLOR_MODE_BAR: Bgr565 = Bgr565::YELLOW;
+const COLOR_CHOKE_BAR: Bgr565 = Bgr565::new(31, 40, 0);
+const COLOR_VELOCITY_BAR: Bgr565 = Bgr565::new(0, 63, 20);
+const COLOR_VOICES_BAR: Bgr565 = Bgr565::BLUE;
+const COLOR_TEMPO_BAR: Bgr565 = Bgr565::new(20, 20, 31);
+const COLOR_ATTACK_BAR: Bgr565 = Bgr565::new(31, 63, 15);
+const COLOR_HOLD_BAR: Bgr565 = Bgr565::new(24, 48, 12);
+const COLOR_RELEASE_BAR: Bgr565 = Bgr565::new(31, 32, 8);
+const COLOR_FILTER_BAR: Bgr565 = Bgr565::new(0, 40, 31);
+const COLOR_DRIVE_BAR: Bgr565 = Bgr565::new(31, 20, 0);
+const COLOR_DELAY_BAR: Bgr565 = Bgr565::new(16, 63, 16);
+const COLOR_REVERB_BAR: Bgr565 = Bgr565::new(24, 24, 31);
+const COLOR_INPUT_GAIN_BAR: Bgr565 = Bgr565::new(31, 24, 24);
+const COLOR_CLIP_MODE_BAR: Bgr565 = Bgr565::new(31, 40, 0);
+const COLOR_ENCODER_LABEL: Bgr565 = Bgr565::WHITE;
+const ENCODER_COLUMN_WIDTH: i32 = WAVEFORM_WIDTH / 8;
+// Output meters stand along the right edge, below the indicator lines and beside the
+// encoder area: the master on the outside, then one thin bar per playing pad.
+const METER_Y_TOP: i32 = 88;
+const METER_Y_BOTTOM: i32 = WAVEFORM_Y_END - 4;
+const MASTER_METER_X: i32 = WAVEFORM_X_END - 10;
+const MASTER_METER_WIDTH: u32 = 8;
+const PAD_METER_WIDTH: u32 = 4;
+const PAD_METER_SPACING: i32 = 6;
+const MAX_PAD_METERS: usize = 12;
+const METER_FLOOR_DB: f32 = -60.0;
+const METER_HOT_DB: f32 = -6.0;
+const COLOR_METER_RMS: Bgr565 = Bgr565::GREEN;
+const COLOR_METER_HOT: Bgr565 = Bgr565::YELLOW;
+const COLOR_METER_PEAK: Bgr565 = Bgr565::WHITE;
+const COLOR_METER_CLIP: Bgr565 = Bgr565::RED;
+const COLOR_RECORDING_WAVEFORM: Bgr565 = Bgr565::new(31, 24, 24);
+const COLOR_CLIP: Bgr565 = Bgr565::RED;
+const INPUT_METER_X: i32 = WAVEFORM_X_START + 2;
+const CLIP_TEXT_POSITION: Point = Point::new(INPUT_METER_X + MASTER_METER_WIDTH as i32 + 6, 28);
+const XRUN_TEXT_POSITION: Point = Point::new(CLIP_TEXT_POSITION.x, CLIP_TEXT_POSITION.y + 24);
+const COLOR_STATUS_ERROR: Bgr565 = Bgr565::RED;
+const STATUS_TEXT_POSITION: Point = Point::new(8, 4);
+const COLOR_BANK_TEXT: Bgr565 = Bgr565::WHITE;
+const BANK_TEXT_POSITION: Point = Point::new(WAVEFORM_X_END - 8, 4);
 
 pub fn draw_screen(
-    push2: &mut Push2,
+    push2: &mut impl Controller,
     state: &mut AppState,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    push2.display.clear(Bgr565::BLACK).unwrap();
+    push2.display().clear(Bgr565::BLACK)?;
 
-    if let Some(key) = state.selected_for_edit {
+    if state.active_recording_key.is_some() {
+        draw_recording(push2, state)?;
+    } else if state.edit_page == EditPage::Setup {
+        draw_setup_page(push2, state)?;
+    } else if let Some(key) = state.selected_for_edit {
         draw_waveform(push2, state, key)?;
         draw_encoders(push2, state, key)?;
     }
+    draw_routing_status(push2, state)?;
+    draw_bank_indicator(push2, state)?;
+    draw_meters(push2, state)?;
 
-    push2.display.flush()?;
+    push2.flush_display()?;
     Ok(())
 }
 
 fn draw_waveform(
-    push2: &mut Push2,
+    push2: &mut impl Controller,
     state: &mut AppState,
     key: u8,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -49,6 +115,13 @@ fn draw_waveform(
                     Ok(p) => peaks = Some(p),
                     Err(e) => warn!("Waveform load error: {}", e),
                 }
+                match hound::WavReader::open(path) {
+                    Ok(reader) => {
+                        let seconds = reader.duration() as f64 / reader.spec().sample_rate as f64;
+                        state.sample_durations.insert(key, seconds);
+                    }
+                    Err(e) => warn!("Sample length read error: {}", e),
+                }
             }
         }
         state.waveform_cache.insert(key, peaks);
@@ -56,23 +129,172 @@ fn draw_waveform(
 
     // 2. Draw Peaks
     if let Some(Some(peaks)) = state.waveform_cache.get(&key) {
-        push2.display.draw_waveform_peaks(peaks, COLOR_WAVEFORM)?;
+        push2.draw_waveform_peaks(peaks, COLOR_WAVEFORM)?;
 
         // 3. Draw Lines
-        let start_pct = *state.sample_start_point.get(&key).unwrap_or(&0.0) as f32;
-        let end_pct = *state.sample_end_point.get(&key).unwrap_or(&1.0) as f32;
+        let pad = state.pad_settings.get(key);
+        let start_pct = pad.start_point as f32;
+        let end_pct = pad.end_point as f32;
 
         let start_x = WAVEFORM_X_START + (start_pct * WAVEFORM_WIDTH as f32).round() as i32;
         let end_x = WAVEFORM_X_START + (end_pct * WAVEFORM_WIDTH as f32).round() as i32;
 
         draw_vertical_line(push2, start_x, COLOR_START_LINE)?;
         draw_vertical_line(push2, end_x, COLOR_STOP_LINE)?;
+
+        // 4. Direction: an arrow where playback begins, pointing into the region
+        if pad.reverse {
+            draw_direction_arrow(push2, end_x, -1)?;
+        } else {
+            draw_direction_arrow(push2, start_x, 1)?;
+        }
+
+        // 5. Envelope over the trimmed region
+        if let Some(&duration) = state.sample_durations.get(&key) {
+            let region_seconds = (pad.end_point - pad.start_point) * duration;
+            if !pad.envelope.is_flat() && region_seconds > 0.0 && end_x > start_x {
+                draw_envelope(
+                    push2,
+                    &pad.envelope,
+                    (start_x, end_x),
+                    region_seconds,
+                    pad.reverse,
+                )?;
+            }
+
+            // 6. Playhead
+            if let Some(&played) = state.playheads.get(&key) {
+                let x = playhead_x(&pad, duration, played);
+                draw_vertical_line(push2, x, COLOR_PLAYHEAD)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Where a pad that has played `played` seconds of its sound is in its `duration`-second
+/// sample. Stretched sounds cover the source at the tempo; reversed ones run from the end.
+fn playhead_x(pad: &PadSettings, duration: f64, played: f64) -> i32 {
+    let source_seconds = match pad.pitch_mode {
+        PitchMode::Stretch => played * pad.tempo,
+        PitchMode::Resample => played,
+    };
+    let start = pad.start_point * duration;
+    let end = pad.end_point * duration;
+    let position = if pad.reverse {
+        end - source_seconds
+    } else {
+        start + source_seconds
+    };
+    let fraction = if duration > 0.0 {
+        position.clamp(start, end) / duration
+    } else {
+        0.0
+    };
+    WAVEFORM_X_START + (fraction * WAVEFORM_WIDTH as f64).round() as i32
+}
+
+/// Plots the envelope's gain between `start_x` and `end_x`, following playback
+/// direction.
+fn draw_envelope(
+    push2: &mut impl Controller,
+    envelope: &Envelope,
+    (start_x, end_x): (i32, i32),
+    region_seconds: f64,
+    reverse: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let seconds_per_pixel = region_seconds / (end_x - start_x) as f64;
+    let height = (ENVELOPE_Y_BOTTOM - ENVELOPE_Y_TOP) as f64;
+    let points: Vec<Point> = (start_x..=end_x)
+        .map(|x| {
+            let offset = if reverse { end_x - x } else { x - start_x };
+            let gain = envelope.gain_at(offset as f64 * seconds_per_pixel, region_seconds);
+            Point::new(x, ENVELOPE_Y_BOTTOM - (gain * height).round() as i32)
+        })
+        .collect();
+    Polyline::new(&points)
+        .into_styled(PrimitiveStyle::with_stroke(COLOR_ENVELOPE, 1))
+        .draw(push2.display())?;
+    Ok(())
+}
+
+/// The input while a pad records: a waveform scrolling in from the right, the input meter,
+/// the elapsed time and a clip warning.
+fn draw_recording(
+    push2: &mut impl Controller,
+    state: &AppState,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let monitor = &state.input_monitor;
+    let peaks = monitor.recent_peaks(WAVEFORM_WIDTH as usize);
+    let mid_y = (WAVEFORM_Y_START + WAVEFORM_Y_END) / 2;
+    let half_height = (WAVEFORM_Y_END - WAVEFORM_Y_START) as f32 / 2.0;
+    let first_x = WAVEFORM_X_END - peaks.len() as i32;
+    for (i, &peak) in peaks.iter().enumerate() {
+        let x = first_x + i as i32;
+        let extent = (peak.min(1.0) * half_height).round() as i32;
+        let color = if peak >= 1.0 {
+            COLOR_CLIP
+        } else {
+            COLOR_RECORDING_WAVEFORM
+        };
+        Line::new(Point::new(x, mid_y - extent), Point::new(x, mid_y + extent))
+            .into_styled(PrimitiveStyle::with_stroke(color, 1))
+            .draw(push2.display())?;
+    }
+
+    draw_level_meter(push2, INPUT_METER_X, MASTER_METER_WIDTH, &monitor.level)?;
+
+    if monitor.is_armed() {
+        draw_indicator_line(push2, 2, "ARMED")?;
+    } else {
+        let elapsed = monitor.elapsed().as_secs_f64();
+        let minutes = (elapsed / 60.0).floor() as u64;
+        let seconds = elapsed - minutes as f64 * 60.0;
+        draw_indicator_line(push2, 2, &format!("REC {}:{:04.1}", minutes, seconds))?;
+    }
+
+    if monitor.is_clipped() {
+        let style = MonoTextStyle::new(&FONT_10X20, COLOR_CLIP);
+        Text::with_baseline("CLIP", CLIP_TEXT_POSITION, style, Baseline::Top)
+            .draw(push2.display())?;
+    }
+    draw_overflows(push2, monitor)?;
+    Ok(())
+}
+
+/// Warns once the capture callback has had to drop input because recording fell behind.
+fn draw_overflows(
+    push2: &mut impl Controller,
+    monitor: &InputMonitor,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let overflows = monitor.overflows();
+    if overflows > 0 {
+        let style = MonoTextStyle::new(&FONT_10X20, COLOR_CLIP);
+        let text = format!("XRUN {}", overflows);
+        Text::with_baseline(&text, XRUN_TEXT_POSITION, style, Baseline::Top)
+            .draw(push2.display())?;
     }
     Ok(())
 }
 
+fn draw_direction_arrow(
+    push2: &mut impl Controller,
+    x: i32,
+    direction: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mid_y = (WAVEFORM_Y_START + WAVEFORM_Y_END) / 2;
+    Triangle::new(
+        Point::new(x, mid_y - DIRECTION_ARROW_SIZE),
+        Point::new(x, mid_y + DIRECTION_ARROW_SIZE),
+        Point::new(x + direction * DIRECTION_ARROW_SIZE, mid_y),
+    )
+    .into_styled(PrimitiveStyle::with_fill(COLOR_DIRECTION_ARROW))
+    .draw(push2.display())?;
+    Ok(())
+}
+
 fn draw_vertical_line(
-    push2: &mut Push2,
+    push2: &mut impl Controller,
     x: i32,
     color: Bgr565,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -81,46 +303,360 @@ fn draw_vertical_line(
         Point::new(x, WAVEFORM_Y_END),
     )
     .into_styled(PrimitiveStyle::with_stroke(color, 1))
-    .draw(&mut push2.display)?;
+    .draw(push2.display())?;
     Ok(())
 }
 
 fn draw_encoders(
-    push2: &mut Push2,
+    push2: &mut impl Controller,
     state: &mut AppState,
     key: u8,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pad = state.pad_settings.get(key);
+    draw_indicator_line(push2, 2, state.edit_page.label())?;
+    if state.renders.is_pending(key) {
+        draw_indicator_line(push2, 4, "RENDERING")?;
+    }
+    match state.edit_page {
+        EditPage::Sample => draw_sample_page(push2, &pad),
+        EditPage::Clip => draw_clip_page(push2, &pad),
+        EditPage::Effects => draw_effects_page(push2, &pad),
+        // Drawn by draw_setup_page instead, since it has no pad.
+        EditPage::Setup => Ok(()),
+    }
+}
+
+fn draw_sample_page(
+    push2: &mut impl Controller,
+    pad: &PadSettings,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Volume (Track 1)
-    let vol = *state.playback_volume.get(&key).unwrap_or(&1.0);
+    let vol = pad.volume;
     let vol_norm = ((vol - -30.0) / (15.0 - -30.0)).clamp(0.0, 1.0);
     draw_single_encoder(push2, 0, vol_norm, COLOR_VOLUME_BAR)?;
 
     // Pitch (Track 2)
-    let pitch = *state.pitch_shift_semitones.get(&key).unwrap_or(&0.0);
+    let pitch = pad.pitch_semitones;
     let pitch_norm = ((pitch + 12.0) / 24.0).clamp(0.0, 1.0);
     draw_single_encoder(push2, 1, pitch_norm, COLOR_PITCH_BAR)?;
 
     // Start (Track 3)
-    let start = *state.sample_start_point.get(&key).unwrap_or(&0.0);
+    let start = pad.start_point;
     draw_single_encoder(push2, 2, start, COLOR_START_LINE)?;
 
     // End (Track 4)
-    let end = *state.sample_end_point.get(&key).unwrap_or(&1.0);
+    let end = pad.end_point;
     draw_single_encoder(push2, 3, end, COLOR_STOP_LINE)?;
 
+    // Playback Mode (Track 5)
+    let mode_norm = pad.mode.index() as f64 / (PadMode::ALL.len() - 1) as f64;
+    draw_single_encoder(push2, 4, mode_norm, COLOR_MODE_BAR)?;
+    draw_encoder_label(push2, 4, pad.mode.label())?;
+
+    // Choke Group (Track 6)
+    let group = pad.choke_group.unwrap_or(0);
+    draw_single_encoder(
+        push2,
+        5,
+        group as f64 / CHOKE_GROUP_COUNT as f64,
+        COLOR_CHOKE_BAR,
+    )?;
+    let choke_label = match pad.choke_group {
+        Some(group) => format!("CHOKE {}", group),
+        None => "NO CHOKE".to_string(),
+    };
+    draw_encoder_label(push2, 5, &choke_label)?;
+
+    // Velocity Curve (Track 7)
+    let curve_index = pad.velocity_curve.map_or(0, |curve| curve.index() + 1);
+    draw_single_encoder(
+        push2,
+        6,
+        curve_index as f64 / VelocityCurve::ALL.len() as f64,
+        COLOR_VELOCITY_BAR,
+    )?;
+    let curve_label = match pad.velocity_curve {
+        Some(curve) => format!("VEL {}", curve.label()),
+        None => "VEL GLOBAL".to_string(),
+    };
+    draw_encoder_label(push2, 6, &curve_label)?;
+
+    // Voices (Track 8)
+    let voices_norm = (pad.voices.max(1) - 1) as f64 / (MAX_VOICES - 1) as f64;
+    draw_single_encoder(push2, 7, voices_norm, COLOR_VOICES_BAR)?;
+    draw_encoder_label(push2, 7, &format!("VOICES {}", pad.voices))?;
+
+    Ok(())
+}
+
+fn draw_clip_page(
+    push2: &mut impl Controller,
+    pad: &PadSettings,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Pitch Mode (Track 1)
+    let stretch_norm = match pad.pitch_mode {
+        PitchMode::Resample => 0.0,
+        PitchMode::Stretch => 1.0,
+    };
+    draw_single_encoder(push2, 0, stretch_norm, COLOR_PITCH_BAR)?;
+    draw_encoder_label(push2, 0, pad.pitch_mode.label())?;
+
+    // Tempo (Track 2), only used when stretching
+    let tempo_norm = ((pad.tempo - MIN_TEMPO) / (MAX_TEMPO - MIN_TEMPO)).clamp(0.0, 1.0);
+    draw_single_encoder(push2, 1, tempo_norm, COLOR_TEMPO_BAR)?;
+    let tempo_label = match pad.pitch_mode {
+        PitchMode::Stretch => format!("TEMPO {:.2}x", pad.tempo),
+        PitchMode::Resample => "TEMPO -".to_string(),
+    };
+    draw_encoder_label(push2, 1, &tempo_label)?;
+
+    // Attack (Track 5)
+    let envelope = &pad.envelope;
+    draw_single_encoder(
+        push2,
+        4,
+        envelope.attack_ms / MAX_ATTACK_MS,
+        COLOR_ATTACK_BAR,
+    )?;
+    draw_encoder_label(push2, 4, &format!("ATK {:.0}ms", envelope.attack_ms))?;
+
+    // Hold (Track 6)
+    let hold_norm = envelope
+        .hold_ms
+        .map_or(0.0, |hold_ms| hold_ms / MAX_HOLD_MS);
+    draw_single_encoder(push2, 5, hold_norm, COLOR_HOLD_BAR)?;
+    let hold_label = match envelope.hold_ms {
+        Some(hold_ms) => format!("HOLD {:.0}ms", hold_ms),
+        None => "HOLD OFF".to_string(),
+    };
+    draw_encoder_label(push2, 5, &hold_label)?;
+
+    // Release (Track 7)
+    draw_single_encoder(
+        push2,
+        6,
+        envelope.release_ms / MAX_RELEASE_MS,
+        COLOR_RELEASE_BAR,
+    )?;
+    draw_encoder_label(push2, 6, &format!("REL {:.0}ms", envelope.release_ms))?;
+
+    Ok(())
+}
+
+fn draw_effects_page(
+    push2: &mut impl Controller,
+    pad: &PadSettings,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let effects = &pad.effects;
+
+    // Filter Type (Track 1)
+    let filter_norm = effects.filter.index() as f64 / (FilterKind::ALL.len() - 1) as f64;
+    draw_single_encoder(push2, 0, filter_norm, COLOR_FILTER_BAR)?;
+    draw_encoder_label(push2, 0, effects.filter.label())?;
+
+    // Cutoff (Track 2), on a log scale
+    let cutoff_norm =
+        (effects.cutoff_hz / MIN_CUTOFF_HZ).ln() / (MAX_CUTOFF_HZ / MIN_CUTOFF_HZ).ln();
+    draw_single_encoder(push2, 1, cutoff_norm, COLOR_FILTER_BAR)?;
+    let cutoff_label = if effects.cutoff_hz >= 1_000.0 {
+        format!("{:.1}kHz", effects.cutoff_hz / 1_000.0)
+    } else {
+        format!("{:.0}Hz", effects.cutoff_hz)
+    };
+    draw_encoder_label(push2, 1, &cutoff_label)?;
+
+    // Drive (Track 3)
+    draw_single_encoder(push2, 2, effects.drive_db / MAX_DRIVE_DB, COLOR_DRIVE_BAR)?;
+    draw_encoder_label(push2, 2, &format!("DRIVE {:.1}dB", effects.drive_db))?;
+
+    // Delay (Track 4)
+    draw_single_encoder(push2, 3, effects.delay_mix, COLOR_DELAY_BAR)?;
+    draw_encoder_label(
+        push2,
+        3,
+        &format!("DELAY {:.0}%", effects.delay_mix * 100.0),
+    )?;
+
+    // Reverb (Track 5)
+    draw_single_encoder(push2, 4, effects.reverb_mix, COLOR_REVERB_BAR)?;
+    draw_encoder_label(
+        push2,
+        4,
+        &format!("REVERB {:.0}%", effects.reverb_mix * 100.0),
+    )?;
+
+    Ok(())
+}
+
+fn draw_setup_page(
+    push2: &mut impl Controller,
+    state: &AppState,
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_indicator_line(push2, 2, state.edit_page.label())?;
+
+    // Input Gain (Track 1)
+    let gain_norm = (state.input_gain_db + MAX_INPUT_GAIN_DB) / (2.0 * MAX_INPUT_GAIN_DB);
+    draw_single_encoder(push2, 0, gain_norm, COLOR_INPUT_GAIN_BAR)?;
+    draw_encoder_label(push2, 0, &format!("IN {:+.1}dB", state.input_gain_db))?;
+
+    // Clip Mode (Track 2)
+    let mode = state.input_clip_mode;
+    let mode_norm = mode.index() as f64 / (InputClipMode::ALL.len() - 1) as f64;
+    draw_single_encoder(push2, 1, mode_norm, COLOR_CLIP_MODE_BAR)?;
+    draw_encoder_label(push2, 1, mode.label())?;
+
+    draw_level_meter(
+        push2,
+        INPUT_METER_X,
+        MASTER_METER_WIDTH,
+        &state.input_monitor.level,
+    )?;
+    draw_overflows(push2, &state.input_monitor)?;
+    Ok(())
+}
+
+fn draw_encoder_label(
+    push2: &mut impl Controller,
+    index: usize,
+    label: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let position = Point::new(index as i32 * ENCODER_COLUMN_WIDTH + 8, WAVEFORM_Y_END - 24);
+    let style = MonoTextStyle::new(&FONT_10X20, COLOR_ENCODER_LABEL);
+    Text::with_baseline(label, position, style, Baseline::Top).draw(push2.display())?;
     Ok(())
 }
 
 fn draw_single_encoder(
-    push2: &mut Push2,
+    push2: &mut impl Controller,
     index: usize,
     normalized_value: f64,
     color: Bgr565,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let val = (normalized_value * 127.0) as i32;
-    push2
-        .display
-        .draw_encoder_outline(index as u8, COLOR_ENCODER_OUTLINE)?;
-    push2.display.draw_encoder_bar(index as u8, val, color)?;
+    push2.draw_encoder_outline(index as u8, COLOR_ENCODER_OUTLINE)?;
+    push2.draw_encoder_bar(index as u8, val, color)?;
+    Ok(())
+}
+
+fn draw_routing_status(
+    push2: &mut impl Controller,
+    state: &AppState,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let message = match &state.routing_status {
+        Some(RoutingStatus::Missing(nodes)) => format!("WAITING FOR {}", nodes.join(", ")),
+        Some(RoutingStatus::Failed(reason)) => format!("ROUTING FAILED: {}", reason),
+        Some(RoutingStatus::Connected(_)) | None => return Ok(()),
+    };
+    let style = MonoTextStyle::new(&FONT_10X20, COLOR_STATUS_ERROR);
+    Text::with_baseline(&message, STATUS_TEXT_POSITION, style, Baseline::Top)
+        .draw(push2.display())?;
     Ok(())
-}
\ No newline at end of file
+}
+
+fn draw_bank_indicator(
+    push2: &mut impl Controller,
+    state: &AppState,
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_indicator_line(push2, 0, &format!("BANK {}", state.current_bank + 1))?;
+    draw_indicator_line(push2, 1, &format!("VEL {}", state.velocity_curve.label()))?;
+    draw_master_indicator(push2, state)
+}
+
+/// Master gain, plus the limiter's gain reduction while it is working.
+fn draw_master_indicator(
+    push2: &mut impl Controller,
+    state: &AppState,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut text = format!("MST {:+.1}dB", state.master.gain_db());
+    let reduction_db = state.master.reduction_db();
+    if reduction_db >= 0.1 {
+        text.push_str(&format!(" GR -{:.1}", reduction_db));
+    }
+    draw_indicator_line(push2, 3, &text)?;
+    Ok(())
+}
+
+fn draw_meters(
+    push2: &mut impl Controller,
+    state: &AppState,
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_level_meter(
+        push2,
+        MASTER_METER_X,
+        MASTER_METER_WIDTH,
+        &state.meters.master,
+    )?;
+    let mut playing: Vec<u8> = state.playing_pads.iter().copied().collect();
+    playing.sort_unstable();
+    for (i, key) in playing.into_iter().take(MAX_PAD_METERS).enumerate() {
+        let x = MASTER_METER_X - (i as i32 + 1) * PAD_METER_SPACING;
+        draw_level_meter(push2, x, PAD_METER_WIDTH, state.meters.pad(key))?;
+    }
+    Ok(())
+}
+
+/// A vertical meter: a filled bar for RMS and a line for the peak, on a dB scale.
+fn draw_level_meter(
+    push2: &mut impl Controller,
+    x: i32,
+    width: u32,
+    meter: &LevelMeter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let height = METER_Y_BOTTOM - METER_Y_TOP;
+    let level_y = |amplitude: f32| {
+        let db = 20.0 * amplitude.max(1e-6).log10();
+        let norm = ((db - METER_FLOOR_DB) / -METER_FLOOR_DB).clamp(0.0, 1.0);
+        METER_Y_BOTTOM - (norm * height as f32).round() as i32
+    };
+
+    let rms = meter.rms();
+    let rms_y = level_y(rms);
+    if rms_y < METER_Y_BOTTOM {
+        let hot = 20.0 * rms.max(1e-6).log10() >= METER_HOT_DB;
+        let color = if hot {
+            COLOR_METER_HOT
+        } else {
+            COLOR_METER_RMS
+        };
+        Rectangle::new(
+            Point::new(x, rms_y),
+            Size::new(width, (METER_Y_BOTTOM - rms_y) as u32),
+        )
+        .into_styled(PrimitiveStyle::with_fill(color))
+        .draw(push2.display())?;
+    }
+
+    let peak = meter.peak();
+    let peak_y = level_y(peak);
+    if peak_y < METER_Y_BOTTOM {
+        let color = if peak >= 1.0 {
+            COLOR_METER_CLIP
+        } else {
+            COLOR_METER_PEAK
+        };
+        Line::new(
+            Point::new(x, peak_y),
+            Point::new(x + width as i32 - 1, peak_y),
+        )
+        .into_styled(PrimitiveStyle::with_stroke(color, 1))
+        .draw(push2.display())?;
+    }
+    Ok(())
+}
+
+/// Right-aligned text in the top-right corner, one line per `line`.
+fn draw_indicator_line(
+    push2: &mut impl Controller,
+    line: i32,
+    text: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let character_style = MonoTextStyle::new(&FONT_10X20, COLOR_BANK_TEXT);
+    let text_style = TextStyleBuilder::new()
+        .alignment(Alignment::Right)
+        .baseline(Baseline::Top)
+        .build();
+    let position =
+        BANK_TEXT_POSITION + Point::new(0, line * FONT_10X20.character_size.height as i32);
+    Text::with_text_style(text, position, character_style, text_style).draw(push2.display())?;
+    Ok(())
+}
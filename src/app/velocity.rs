@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+
+// The softest hit still plays, just this much quieter than a full-velocity hit.
+const VELOCITY_FLOOR_DB: f64 = -40.0;
+const MAX_VELOCITY: f64 = 127.0;
+
+/// How pad velocity maps to playback gain.
+#[derive(Serialize, Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum VelocityCurve {
+    /// Amplitude proportional to velocity.
+    #[default]
+    Linear,
+    /// Decibels proportional to velocity, so soft hits stay usable.
+    Logarithmic,
+    /// Every hit plays at full level.
+    Fixed,
+    /// Amplitudes interpolated from `velocity.table` in the config.
+    Custom,
+}
+
+impl VelocityCurve {
+    pub const ALL: [VelocityCurve; 4] = [
+        VelocityCurve::Linear,
+        VelocityCurve::Logarithmic,
+        VelocityCurve::Fixed,
+        VelocityCurve::Custom,
+    ];
+
+    pub fn index(self) -> usize {
+        Self::ALL
+            .iter()
+            .position(|&curve| curve == self)
+            .unwrap_or(0)
+    }
+
+    /// The following curve, wrapping around after the last one.
+    pub fn next(self) -> Self {
+        Self::ALL[(self.index() + 1) % Self::ALL.len()]
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            VelocityCurve::Linear => "LINEAR",
+            VelocityCurve::Logarithmic => "LOG",
+            VelocityCurve::Fixed => "FIXED",
+            VelocityCurve::Custom => "CUSTOM",
+        }
+    }
+
+    /// Gain offset in dB for a hit at `velocity` (0-127). `table` is only used by `Custom`.
+    pub fn gain_db(self, velocity: u8, table: &[f64]) -> f64 {
+        let position = (velocity as f64 / MAX_VELOCITY).clamp(0.0, 1.0);
+        match self {
+            VelocityCurve::Linear => amplitude_to_db(position),
+            VelocityCurve::Logarithmic => VELOCITY_FLOOR_DB * (1.0 - position),
+            VelocityCurve::Fixed => 0.0,
+            VelocityCurve::Custom => amplitude_to_db(interpolate(table, position)),
+        }
+    }
+}
+
+fn amplitude_to_db(amplitude: f64) -> f64 {
+    if amplitude <= 0.0 {
+        return VELOCITY_FLOOR_DB;
+    }
+    (20.0 * amplitude.log10()).max(VELOCITY_FLOOR_DB)
+}
+
+/// Reads `table` as evenly spaced points from velocity 0 to 127.
+fn interpolate(table: &[f64], position: f64) -> f64 {
+    match table {
+        [] => 1.0,
+        [only] => *only,
+        _ => {
+            let scaled = position * (table.len() - 1) as f64;
+            let index = (scaled.floor() as usize).min(table.len() - 2);
+            let fraction = scaled - index as f64;
+            table[index] + (table[index + 1] - table[index]) * fraction
+        }
+    }
+}
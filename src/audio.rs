@@ -1,2 +1,9 @@
 pub mod capture;
+pub mod effects;
+pub mod envelope;
+pub mod master;
+pub mod meter;
 pub mod player;
+pub mod routing;
+pub mod stretch;
+pub mod trim;
@@ -1,221 +1,789 @@
+pub mod file_backend;
+pub mod gain;
+pub mod monitor;
+pub mod pipewire_backend;
+pub mod ring;
 
 use crate::app::state::{AppCommand, AudioCommand};
+use crate::audio::capture::gain::GainStage;
+use crate::audio::capture::monitor::{COLUMN_SECONDS, InputMonitor};
+use crate::audio::capture::ring::{RingConsumer, RingProducer, ring};
+use crate::audio::meter::Ballistics;
+use crate::audio::trim::TrimPoints;
+use crate::config::CaptureConfig;
 use hound::{SampleFormat, WavSpec, WavWriter};
-use pipewire as pw;
-use pw::{properties::properties, spa};
-use spa::param::format::{MediaSubtype, MediaType};
-use spa::param::format_utils;
-use spa::pod::Pod;
+use log::{error, info};
 use std::collections::VecDeque;
-use std::convert::TryInto;
-use std::fs;
-use std::mem;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use std::sync::{
-    Arc, Mutex,
-    mpsc::{Receiver, Sender},
+    Arc,
+    atomic::{AtomicU64, Ordering},
+    mpsc::{Receiver, RecvTimeoutError, Sender},
 };
 use std::thread;
+use std::time::Duration;
 
-const PRE_BUFFER_SECONDS: u32 = 1;
+// How often a recording's WAV header is rewritten, in seconds of audio.
+const HEADER_UPDATE_SECONDS: u64 = 1;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CaptureFormat {
+    pub rate: u32,
+    pub channels: u32,
+}
 
-#[derive(Debug, PartialEq, Clone)]
 enum State {
     Listening,
-    Recording(PathBuf),
+    /// Waiting for the input to cross the threshold before recording to the path.
+    Armed(PathBuf),
+    Recording(Box<Recording>),
 }
 
-struct UserData {
-    format: Option<spa::param::audio::AudioInfoRaw>,
+/// A recording in progress, streamed to a `.part` file next to its final path.
+struct Recording {
+    path: PathBuf,
+    part_path: PathBuf,
+    writer: WavWriter<BufWriter<File>>,
+    frames: u64,
+    /// Frames written since the WAV header was last brought up to date.
+    unflushed_frames: u64,
+    /// For armed recordings, how many silent frames in a row end the recording.
+    silence_limit_frames: Option<u64>,
+    /// Silent frames at the end of the input so far. They stay buffered until sound
+    /// returns, so a recording stopped by silence doesn't end in it.
+    silent_frames: u64,
+    /// First and last frames written that reach the trim threshold.
+    audible: Option<(u64, u64)>,
+}
+
+/// Recording state machine: always keeps a rolling history of the input, which supplies
+/// the pre-roll and retroactive saves, and streams everything to disk while recording.
+pub struct CaptureEngine {
+    config: CaptureConfig,
+    format: Option<CaptureFormat>,
     state: State,
+    /// Processed audio that hasn't reached the recording's file yet.
     buffer: VecDeque<f32>,
+    history: VecDeque<f32>,
     pre_buffer_max_samples: usize,
+    history_max_samples: usize,
+    /// Frame level, after gain and clip mode, that starts an armed recording.
+    arm_threshold: f32,
+    /// Level below which auto-trim treats written audio as silence.
+    trim_threshold: f32,
+    stage: GainStage,
+    monitor: Arc<InputMonitor>,
+    ballistics: Ballistics,
+    /// Loudest frame and frame count of the scrolling-waveform column being filled.
+    column_peak: f32,
+    column_frames: usize,
+    /// A recording that couldn't be started, still to be reported to the app.
+    failed: Option<PathBuf>,
 }
 
-fn save_recording_from_buffer(
-    buffer: VecDeque<f32>,
-    format: &spa::param::audio::AudioInfoRaw,
-    filename: &Path,
-) {
-    if buffer.is_empty() {
-        return;
+impl Recording {
+    fn create(path: PathBuf, format: CaptureFormat) -> Option<Self> {
+        let part_path = path.with_extension("wav.part");
+        match create_wav_writer(&part_path, format) {
+            Ok(writer) => Some(Self {
+                path,
+                part_path,
+                writer,
+                frames: 0,
+                unflushed_frames: 0,
+                silence_limit_frames: None,
+                silent_frames: 0,
+                audible: None,
+            }),
+            Err(e) => {
+                error!("Cannot record to {}: {}", part_path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Appends interleaved samples to the file, noting which frames reach
+    /// `trim_threshold`. Returns false if the file can't be written.
+    fn write(
+        &mut self,
+        samples: impl Iterator<Item = f32>,
+        channels: usize,
+        trim_threshold: f32,
+    ) -> bool {
+        let first_sample = self.frames * channels as u64;
+        let mut written = 0;
+        let mut ok = true;
+        for sample in samples {
+            if let Err(e) = self.writer.write_sample(sample) {
+                error!("Failed to write {}: {}", self.part_path.display(), e);
+                ok = false;
+                break;
+            }
+            if sample.abs() >= trim_threshold {
+                self.mark_audible((first_sample + written) / channels as u64);
+            }
+            written += 1;
+        }
+        let frames = written / channels as u64;
+        self.frames += frames;
+        self.unflushed_frames += frames;
+        ok
+    }
+
+    fn mark_audible(&mut self, frame: u64) {
+        self.audible.get_or_insert((frame, frame)).1 = frame;
+    }
+}
+
+/// A finished recording whose file still has to be closed and moved into place.
+pub struct PendingSave {
+    recording: Box<Recording>,
+    /// Audio that still has to be written first, such as a snapshot of the history.
+    unwritten: Vec<f32>,
+}
+
+impl PendingSave {
+    /// Where the audio in the file begins and ends, or `None` if it is all below the trim
+    /// threshold.
+    pub fn trim_points(&self) -> Option<TrimPoints> {
+        let recording = &self.recording;
+        let (first, last) = recording.audible?;
+        let sample_rate = recording.writer.spec().sample_rate;
+        Some(TrimPoints::around(
+            first,
+            last,
+            recording.frames,
+            sample_rate,
+        ))
+    }
+
+    /// Writes what is left, finalizes the WAV file, renames it to the pad's path and
+    /// returns that path.
+    pub fn finish(self) -> PathBuf {
+        let Recording {
+            path,
+            part_path,
+            mut writer,
+            frames,
+            ..
+        } = *self.recording;
+        for sample in self.unwritten {
+            if let Err(e) = writer.write_sample(sample) {
+                error!("Failed to write {}: {}", part_path.display(), e);
+                break;
+            }
+        }
+        if let Err(e) = writer.finalize() {
+            error!("Failed to finalize {}: {}", part_path.display(), e);
+        }
+        if frames == 0 {
+            let _ = fs::remove_file(&part_path);
+        } else if let Err(e) = fs::rename(&part_path, &path) {
+            error!("Failed to move {} into place: {}", part_path.display(), e);
+        }
+        path
+    }
+}
+
+impl CaptureEngine {
+    pub fn new(config: CaptureConfig, monitor: Arc<InputMonitor>) -> Self {
+        Self {
+            config,
+            format: None,
+            state: State::Listening,
+            buffer: VecDeque::new(),
+            history: VecDeque::new(),
+            pre_buffer_max_samples: 0,
+            history_max_samples: 0,
+            arm_threshold: 10.0_f64.powf(config.arm_threshold_db / 20.0) as f32,
+            trim_threshold: 10.0_f64.powf(config.trim_threshold_db / 20.0) as f32,
+            stage: GainStage::new(config.gain, config.clip_mode),
+            monitor,
+            ballistics: Ballistics::default(),
+            column_peak: 0.0,
+            column_frames: 0,
+            failed: None,
+        }
+    }
+
+    pub fn format(&self) -> Option<CaptureFormat> {
+        self.format
+    }
+
+    pub fn set_format(&mut self, format: CaptureFormat) {
+        self.format = Some(format);
+        self.stage.set_sample_rate(format.rate);
+        let samples_per_second = (format.rate * format.channels) as f64;
+        self.pre_buffer_max_samples =
+            (samples_per_second * self.config.pre_buffer_seconds) as usize;
+        let retro_samples = (samples_per_second * self.config.retro_seconds) as usize;
+        let arm_samples = (samples_per_second * self.config.arm_pre_roll_seconds) as usize;
+        self.history_max_samples = self
+            .pre_buffer_max_samples
+            .max(retro_samples)
+            .max(arm_samples);
+        // Samples of another channel layout would be misread.
+        self.history.clear();
     }
-    if let Some(parent) = filename.parent() {
-        if !parent.exists() {
-            let _ = fs::create_dir_all(parent);
+
+    pub fn is_recording(&self) -> bool {
+        matches!(self.state, State::Recording(_))
+    }
+
+    /// The path of a recording or save that failed to start since the last call.
+    pub fn take_failed(&mut self) -> Option<PathBuf> {
+        self.failed.take()
+    }
+
+    /// Opens a recording's file, remembering the path to report if that fails.
+    fn create_recording(&mut self, path: PathBuf, format: CaptureFormat) -> Option<Recording> {
+        let recording = Recording::create(path.clone(), format);
+        if recording.is_none() {
+            self.failed = Some(path);
         }
+        recording
     }
 
+    /// Applies the input gain and clip mode to a block of interleaved samples, adds it to
+    /// the history and, while recording, to the file. Returns the recording if it just
+    /// reached its maximum length.
+    pub fn process(&mut self, samples: &[f32]) -> Option<PendingSave> {
+        let format = self.format?;
+        self.update_monitor(samples, format);
+        let first_new = self.buffer.len();
+        for frame in samples.chunks(format.channels.max(1) as usize) {
+            self.stage.process_frame(frame, &mut self.buffer);
+        }
+
+        // Trimmed only after an armed recording has taken its pre-roll from it.
+        self.history.extend(self.buffer.range(first_new..));
+        let onset = match self.state {
+            State::Armed(_) => self.find_onset(first_new, format),
+            _ => None,
+        };
+        let scan_from = match onset {
+            Some(onset) => self.trigger(first_new, onset, format),
+            None => first_new,
+        };
+        let excess = self.history.len().saturating_sub(self.history_max_samples);
+        self.history.drain(..excess);
+
+        if self.is_recording() {
+            self.count_silence(scan_from, format);
+            return self.write_buffered(format);
+        }
+        self.buffer.clear();
+        None
+    }
+
+    /// Loudest sample of the buffered frame starting at `index`.
+    fn frame_level(&self, index: usize, channels: usize) -> f32 {
+        self.buffer
+            .range(index..(index + channels).min(self.buffer.len()))
+            .fold(0.0_f32, |peak, s| peak.max(s.abs()))
+    }
+
+    /// Buffer index of the first frame from `from` on that reaches the arm threshold.
+    fn find_onset(&self, from: usize, format: CaptureFormat) -> Option<usize> {
+        let channels = format.channels.max(1) as usize;
+        (from..self.buffer.len())
+            .step_by(channels)
+            .find(|&index| self.frame_level(index, channels) >= self.arm_threshold)
+    }
+
+    /// Turns an armed engine into a recording that starts the arm pre-roll before `onset`.
+    /// Returns where the onset ended up in the buffer.
+    fn trigger(&mut self, first_new: usize, onset: usize, format: CaptureFormat) -> usize {
+        let State::Armed(path) = std::mem::replace(&mut self.state, State::Listening) else {
+            return onset;
+        };
+        let Some(mut recording) = self.create_recording(path, format) else {
+            self.monitor.set_armed(false);
+            self.buffer.clear();
+            return 0;
+        };
+        let channels = format.channels.max(1) as usize;
+        let rate = format.rate as f64;
+        if self.config.arm_silence_seconds > 0.0 {
+            recording.silence_limit_frames = Some((self.config.arm_silence_seconds * rate) as u64);
+        }
+        info!("Input crossed the threshold, recording");
+        self.state = State::Recording(Box::new(recording));
+        self.monitor.set_armed(false);
+
+        // The pre-roll comes from this block first, then from the history before it.
+        let pre_roll = (self.config.arm_pre_roll_seconds * rate) as usize * channels;
+        let block_len = self.buffer.len() - first_new;
+        let from_block = (onset - first_new).min(pre_roll);
+        self.buffer.drain(..onset - from_block);
+        let older_end = self.history.len().saturating_sub(block_len);
+        let older_start = older_end.saturating_sub(pre_roll - from_block);
+        for &sample in self.history.range(older_start..older_end).rev() {
+            self.buffer.push_front(sample);
+        }
+        (older_end - older_start) + from_block
+    }
+
+    /// Updates the run of silent frames at the end of an armed recording's input.
+    fn count_silence(&mut self, from: usize, format: CaptureFormat) {
+        let channels = format.channels.max(1) as usize;
+        let mut silent_frames = match &self.state {
+            State::Recording(recording) if recording.silence_limit_frames.is_some() => {
+                recording.silent_frames
+            }
+            _ => return,
+        };
+        for index in (from..self.buffer.len()).step_by(channels) {
+            if self.frame_level(index, channels) < self.arm_threshold {
+                silent_frames += 1;
+            } else {
+                silent_frames = 0;
+            }
+        }
+        if let State::Recording(recording) = &mut self.state {
+            recording.silent_frames = silent_frames;
+        }
+    }
+
+    /// Moves buffered audio into the recording's file, stopping the recording once it
+    /// reaches the maximum length, falls silent for long enough or can't be written.
+    fn write_buffered(&mut self, format: CaptureFormat) -> Option<PendingSave> {
+        let channels = format.channels.max(1) as usize;
+        let tail_samples = self.tail_samples(format);
+        let State::Recording(recording) = &mut self.state else {
+            return None;
+        };
+        let max_frames = (self.config.max_recording_seconds * format.rate as f64) as u64;
+        let room = max_frames.saturating_sub(recording.frames) as usize * channels;
+        let mut held = 0;
+        let mut silence_reached = false;
+        if let Some(limit) = recording.silence_limit_frames {
+            held = (recording.silent_frames as usize * channels).min(self.buffer.len());
+            if recording.silent_frames >= limit {
+                held -= tail_samples.min(held);
+                silence_reached = true;
+            }
+        }
+        let count = (self.buffer.len() - held).min(room);
+
+        let mut failed =
+            !recording.write(self.buffer.drain(..count), channels, self.trim_threshold);
+        // Keep the header current so the file is playable if we never get to finalize it.
+        if !failed && recording.unflushed_frames >= format.rate as u64 * HEADER_UPDATE_SECONDS {
+            if let Err(e) = recording.writer.flush() {
+                error!("Failed to update {}: {}", recording.part_path.display(), e);
+                failed = true;
+            }
+            recording.unflushed_frames = 0;
+        }
+
+        if recording.frames >= max_frames {
+            info!(
+                "Recording reached the {} s limit, stopping",
+                self.config.max_recording_seconds
+            );
+        } else if silence_reached {
+            info!("Input stayed below the threshold, stopping");
+        } else if !failed {
+            return None;
+        }
+        self.stop()
+    }
+
+    /// Samples of trailing silence a recording keeps for decays: as long as the pre-roll.
+    fn tail_samples(&self, format: CaptureFormat) -> usize {
+        (self.config.arm_pre_roll_seconds * format.rate as f64) as usize
+            * format.channels.max(1) as usize
+    }
+
+    /// Writes the trailing silence an armed recording is holding back, up to the tail an
+    /// automatic stop would keep, so stopping by hand doesn't cut off a decay.
+    fn write_held_tail(&mut self) {
+        let Some(format) = self.format else {
+            return;
+        };
+        let channels = format.channels.max(1) as usize;
+        let tail_samples = self.tail_samples(format);
+        let State::Recording(recording) = &mut self.state else {
+            return;
+        };
+        let max_frames = (self.config.max_recording_seconds * format.rate as f64) as u64;
+        let room = max_frames.saturating_sub(recording.frames) as usize * channels;
+        let count = self.buffer.len().min(tail_samples).min(room);
+        recording.write(self.buffer.drain(..count), channels, self.trim_threshold);
+        self.buffer.clear();
+    }
+
+    fn start(&mut self, path: PathBuf) -> Option<PendingSave> {
+        let format = self.format?;
+        if !matches!(self.state, State::Listening) {
+            return None;
+        }
+        let recording = self.create_recording(path, format)?;
+        self.state = State::Recording(Box::new(recording));
+        self.monitor.start_recording(format.rate);
+        self.column_peak = 0.0;
+        self.column_frames = 0;
+        // The pre-roll goes to disk straight away.
+        let pre_roll = self.history.len().min(self.pre_buffer_max_samples);
+        self.buffer.clear();
+        self.buffer
+            .extend(self.history.range(self.history.len() - pre_roll..));
+        self.write_buffered(format)
+    }
+
+    /// Waits for the input to cross the threshold before recording to `path`.
+    fn arm(&mut self, path: PathBuf) {
+        let Some(format) = self.format else {
+            return;
+        };
+        if matches!(self.state, State::Listening) {
+            self.state = State::Armed(path);
+            self.monitor.start_recording(format.rate);
+            self.monitor.set_armed(true);
+            self.column_peak = 0.0;
+            self.column_frames = 0;
+        }
+    }
+
+    /// Saves the whole rolling history to `path`, whether or not a recording is running.
+    /// Only a snapshot is taken here; it is written when the save is finished.
+    fn save_history(&mut self, path: PathBuf) -> Option<PendingSave> {
+        // With no input yet there is nothing to save, which the app must hear about too.
+        let Some(format) = self.format.filter(|_| !self.history.is_empty()) else {
+            info!("No input history to save to {}", path.display());
+            self.failed = Some(path);
+            return None;
+        };
+        let mut recording = self.create_recording(path, format)?;
+        let channels = format.channels.max(1) as usize;
+        recording.frames = (self.history.len() / channels) as u64;
+        for (index, sample) in self.history.iter().enumerate() {
+            if sample.abs() >= self.trim_threshold {
+                recording.mark_audible((index / channels) as u64);
+            }
+        }
+        Some(PendingSave {
+            recording: Box::new(recording),
+            unwritten: self.history.iter().copied().collect(),
+        })
+    }
+
+    fn stop(&mut self) -> Option<PendingSave> {
+        self.monitor.set_armed(false);
+        match std::mem::replace(&mut self.state, State::Listening) {
+            State::Recording(recording) => Some(PendingSave {
+                recording,
+                unwritten: Vec::new(),
+            }),
+            State::Armed(_) | State::Listening => None,
+        }
+    }
+
+    /// Publishes the input level and, while recording, the waveform columns, elapsed time
+    /// and clipping. Levels are taken after the gain but before the clip mode, so clipping
+    /// shows whatever the mode makes of it.
+    fn update_monitor(&mut self, samples: &[f32], format: CaptureFormat) {
+        let gain = self.stage.gain;
+        let channels = format.channels.max(1) as usize;
+        let frame_levels = samples.chunks(channels).map(|frame| {
+            frame
+                .iter()
+                .fold(0.0_f32, |peak, s| peak.max((s * gain).abs()))
+        });
+        self.ballistics.update(
+            frame_levels.clone(),
+            1.0 / format.rate as f64,
+            &self.monitor.level,
+        );
+        if !self.is_recording() {
+            return;
+        }
+
+        let frames_per_column = ((format.rate as f64 * COLUMN_SECONDS) as usize).max(1);
+        let mut frames = 0;
+        for level in frame_levels {
+            frames += 1;
+            if level >= 1.0 {
+                self.monitor.mark_clipped();
+            }
+            self.column_peak = self.column_peak.max(level);
+            self.column_frames += 1;
+            if self.column_frames == frames_per_column {
+                self.monitor.push_column(self.column_peak);
+                self.column_peak = 0.0;
+                self.column_frames = 0;
+            }
+        }
+        self.monitor.add_recorded_frames(frames);
+    }
+
+    pub fn handle_command(&mut self, command: AudioCommand) -> Option<PendingSave> {
+        match command {
+            AudioCommand::Start(path) => self.start(path),
+            AudioCommand::Arm(path) => {
+                self.arm(path);
+                None
+            }
+            AudioCommand::SaveHistory(path) => self.save_history(path),
+            AudioCommand::SetInputGain(gain) => {
+                self.stage.gain = gain;
+                None
+            }
+            AudioCommand::SetClipMode(mode) => {
+                self.stage.mode = mode;
+                None
+            }
+            AudioCommand::Stop => {
+                self.write_held_tail();
+                self.stop()
+            }
+        }
+    }
+}
+
+// About 2.7 s of 48 kHz stereo between the capture callback and the recording thread.
+pub const RING_CAPACITY: usize = 1 << 18;
+// How often the recording thread empties the ring when no commands arrive.
+const DRAIN_INTERVAL: Duration = Duration::from_millis(5);
+// Samples the recording thread hands to the engine at a time.
+const DRAIN_BLOCK: usize = 4096;
+
+/// The handle a capture backend uses to feed audio into the engine. Runs on the realtime
+/// thread, so it only writes to a preallocated ring and atomics: no locks, no allocation.
+pub struct CaptureInput {
+    producer: RingProducer,
+    /// The latest format, packed as `rate << 32 | channels`; 0 until one is announced.
+    format: Arc<AtomicU64>,
+    monitor: Arc<InputMonitor>,
+}
+
+impl CaptureInput {
+    pub fn set_format(&mut self, format: CaptureFormat) {
+        let packed = ((format.rate as u64) << 32) | format.channels as u64;
+        self.format.store(packed, Ordering::Release);
+    }
+
+    pub fn push_samples(&mut self, samples: &[f32]) {
+        self.push(samples.iter().copied());
+    }
+
+    /// Queues one block of interleaved samples. A block that doesn't fit because the
+    /// recording thread fell behind is dropped whole and counted as an overflow.
+    pub fn push(&mut self, samples: impl ExactSizeIterator<Item = f32>) {
+        if !self.producer.push(samples) {
+            self.monitor.add_overflow();
+        }
+    }
+}
+
+/// The recording thread's end of a `CaptureInput`.
+pub struct CaptureReader {
+    consumer: RingConsumer,
+    format: Arc<AtomicU64>,
+    scratch: Vec<f32>,
+}
+
+impl CaptureReader {
+    /// Passes format changes and everything queued so far on to `engine`. Returns the
+    /// recording if it reached its maximum length on the way.
+    pub fn drain_into(&mut self, engine: &mut CaptureEngine) -> Option<PendingSave> {
+        let packed = self.format.load(Ordering::Acquire);
+        if packed != 0 {
+            let format = CaptureFormat {
+                rate: (packed >> 32) as u32,
+                channels: packed as u32,
+            };
+            if engine.format() != Some(format) {
+                engine.set_format(format);
+            }
+        }
+        let mut finished = None;
+        loop {
+            let count = self.consumer.pop_into(&mut self.scratch);
+            if count == 0 {
+                return finished;
+            }
+            if let Some(pending) = engine.process(&self.scratch[..count]) {
+                finished = Some(pending);
+            }
+        }
+    }
+}
+
+/// Connects a capture backend to the thread that records: the backend writes into the
+/// `CaptureInput`, the recording thread reads from the `CaptureReader`.
+pub fn capture_channel(monitor: Arc<InputMonitor>) -> (CaptureInput, CaptureReader) {
+    let (producer, consumer) = ring(RING_CAPACITY);
+    let format = Arc::new(AtomicU64::new(0));
+    let input = CaptureInput {
+        producer,
+        format: format.clone(),
+        monitor,
+    };
+    let reader = CaptureReader {
+        consumer,
+        format,
+        scratch: vec![0.0; DRAIN_BLOCK],
+    };
+    (input, reader)
+}
+
+/// A source of interleaved f32 audio, such as the PipeWire capture sink or a WAV fixture.
+pub trait CaptureBackend: Send + 'static {
+    /// Announces the format, then pushes samples until the source ends or fails.
+    fn run(self, input: CaptureInput) -> Result<(), Box<dyn Error>>;
+}
+
+fn create_wav_writer(
+    path: &Path,
+    format: CaptureFormat,
+) -> Result<WavWriter<BufWriter<File>>, Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
     let spec = WavSpec {
-        channels: format.channels() as u16,
-        sample_rate: format.rate(),
+        channels: format.channels as u16,
+        sample_rate: format.rate,
         bits_per_sample: 32,
         sample_format: SampleFormat::Float,
     };
+    Ok(WavWriter::create(path, spec)?)
+}
 
-    if let Ok(mut writer) = WavWriter::create(filename, spec) {
-        for &sample in &buffer {
-            let _ = writer.write_sample(sample);
+/// Moves recordings a crash left behind as `pad_*.wav.part` in `dir` into place, with their
+/// header fixed to cover every whole frame that reached the disk. A part whose pad already
+/// has a file is left alone.
+pub fn recover_parts(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let part_path = entry.path();
+        let Some(name) = part_path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if !name.starts_with("pad_") || !name.ends_with(".wav.part") {
+            continue;
+        }
+        let path = part_path.with_extension("");
+        if path.exists() {
+            info!(
+                "Leaving {}: {} already exists",
+                part_path.display(),
+                path.display()
+            );
+            continue;
+        }
+        match repair_wav_header(&part_path).and_then(|()| fs::rename(&part_path, &path)) {
+            Ok(()) => info!("Recovered unfinished recording {}", path.display()),
+            Err(e) => error!("Failed to recover {}: {}", part_path.display(), e),
         }
-        let _ = writer.finalize();
     }
 }
 
-fn handle_audio_commands(
+/// Sets the RIFF and data chunk sizes of a WAV file to what is on disk, dropping a torn
+/// frame at the end.
+fn repair_wav_header(path: &Path) -> std::io::Result<()> {
+    let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+    let mut file = fs::OpenOptions::new().read(true).write(true).open(path)?;
+    let len = file.metadata()?.len();
+    let mut riff = [0u8; 12];
+    file.read_exact(&mut riff)?;
+    if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+        return Err(invalid("not a WAV file"));
+    }
+
+    let mut block_align = None;
+    let mut position = 12;
+    while position + 8 <= len {
+        let mut chunk = [0u8; 8];
+        file.seek(SeekFrom::Start(position))?;
+        file.read_exact(&mut chunk)?;
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as u64;
+        match &chunk[0..4] {
+            b"fmt " => {
+                let mut fmt = [0u8; 14];
+                file.read_exact(&mut fmt)?;
+                block_align = Some(u16::from_le_bytes([fmt[12], fmt[13]]) as u64);
+            }
+            b"data" => {
+                let block_align = block_align
+                    .filter(|&align| align > 0)
+                    .ok_or_else(|| invalid("no format before the data"))?;
+                let start = position + 8;
+                let data_len = (len - start) / block_align * block_align;
+                let riff_len = u32::try_from(start + data_len - 8)
+                    .map_err(|_| invalid("too long for a WAV file"))?;
+                file.seek(SeekFrom::Start(4))?;
+                file.write_all(&riff_len.to_le_bytes())?;
+                file.seek(SeekFrom::Start(position + 4))?;
+                file.write_all(&(data_len as u32).to_le_bytes())?;
+                return file.set_len(start + data_len);
+            }
+            _ => {}
+        }
+        position += 8 + size + size % 2;
+    }
+    Err(invalid("no data chunk"))
+}
+
+/// Owns the engine: applies commands and keeps the ring drained, so the capture callback
+/// never waits on anything.
+fn run_recording_thread(
+    mut engine: CaptureEngine,
+    mut reader: CaptureReader,
     rx: Receiver<AudioCommand>,
-    data: Arc<Mutex<UserData>>,
     app_tx: Sender<AppCommand>,
 ) {
-    for command in rx {
-        let mut save_data: Option<(VecDeque<f32>, spa::param::audio::AudioInfoRaw, PathBuf)> = None;
-        {
-            let mut user_data = data.lock().unwrap();
-            match command {
-                AudioCommand::Start(path) => {
-                    if user_data.format.is_some() {
-                        if let State::Listening = user_data.state {
-                            user_data.state = State::Recording(path);
-                        }
-                    }
+    // Saves are finished on their own thread: writing a history snapshot can take a while,
+    // and the ring has to keep draining meanwhile.
+    let finish = |pending: PendingSave| {
+        let app_tx = app_tx.clone();
+        thread::spawn(move || {
+            let trim = pending.trim_points();
+            let path = pending.finish();
+            let _ = app_tx.send(AppCommand::FileSaved(path, trim));
+        });
+    };
+    loop {
+        match rx.recv_timeout(DRAIN_INTERVAL) {
+            Ok(command) => {
+                // Audio that arrived before the command belongs before it.
+                if let Some(pending) = reader.drain_into(&mut engine) {
+                    finish(pending);
                 }
-                AudioCommand::Stop => {
-                    let old_state = std::mem::replace(&mut user_data.state, State::Listening);
-                    if let State::Recording(save_path) = old_state {
-                        let buffer_to_save = std::mem::take(&mut user_data.buffer);
-                        if let Some(fmt) = user_data.format {
-                            save_data = Some((buffer_to_save, fmt, save_path));
-                        }
-                    }
+                if let Some(pending) = engine.handle_command(command) {
+                    finish(pending);
                 }
             }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
         }
-
-        if let Some((buffer, format, path)) = save_data {
-            save_recording_from_buffer(buffer, &format, &path);
-            let _ = app_tx.send(AppCommand::FileSaved(path));
+        if let Some(pending) = reader.drain_into(&mut engine) {
+            finish(pending);
+        }
+        if let Some(path) = engine.take_failed() {
+            let _ = app_tx.send(AppCommand::RecordingFailed(path));
         }
     }
 }
 
-pub fn run_capture_loop(
+pub fn run_capture_loop<B: CaptureBackend>(
+    backend: B,
     rx: Receiver<AudioCommand>,
     app_tx: Sender<AppCommand>,
-) -> Result<(), pw::Error> {
-    pw::init();
-    let mainloop = pw::main_loop::MainLoopRc::new(None)?;
-    let context = pw::context::ContextRc::new(&mainloop, None)?;
-    let core = context.connect_rc(None)?;
-
-    let data = Arc::new(Mutex::new(UserData {
-        format: None,
-        state: State::Listening,
-        buffer: VecDeque::new(),
-        pre_buffer_max_samples: 0,
-    }));
-
-    let props = properties! {
-        *pw::keys::MEDIA_TYPE => "Audio",
-        *pw::keys::MEDIA_CATEGORY => "Capture",
-        *pw::keys::MEDIA_ROLE => "Music",
-        *pw::keys::STREAM_CAPTURE_SINK => "true",
-    };
-
-    let stream = pw::stream::StreamBox::new(&core, "audio-capture", props)?;
-
-    let _listener = stream
-        .add_local_listener_with_user_data(data.clone())
-        .param_changed(|_, user_data_arc, id, param| {
-            if let Some(param) = param {
-                if id == pw::spa::param::ParamType::Format.as_raw() {
-                    if let Ok((MediaType::Audio, MediaSubtype::Raw)) =
-                        format_utils::parse_format(param)
-                    {
-                        let mut user_data = user_data_arc.lock().unwrap();
-                        let mut info = spa::param::audio::AudioInfoRaw::new();
-                        if info.parse(param).is_ok() {
-                            user_data.format = Some(info);
-                            user_data.pre_buffer_max_samples =
-                                (info.rate() * info.channels() * PRE_BUFFER_SECONDS) as usize;
-                        }
-                    }
-                }
-            }
-        })
-        .process(|stream, user_data_arc| {
-            const GAIN_FACTOR: f32 = 2.0;
-            let mut user_data = user_data_arc.lock().unwrap();
-            if user_data.format.is_none() {
-                return;
-            }
-
-            match stream.dequeue_buffer() {
-                None => {}
-                Some(mut buffer) => {
-                    let datas = buffer.datas_mut();
-                    if !datas.is_empty() {
-                        let data = &mut datas[0];
-                        let n_samples = data.chunk().size() / (mem::size_of::<f32>() as u32);
-                        if let Some(samples) = data.data() {
-                            let mut all_samples = Vec::with_capacity(n_samples as usize);
-                            for n in 0..(n_samples as usize) {
-                                let start = n * mem::size_of::<f32>();
-                                let end = start + mem::size_of::<f32>();
-                                let chan = &samples[start..end];
-                                let sample = f32::from_le_bytes(chan.try_into().unwrap());
-                                let amplified_sample = sample * GAIN_FACTOR;
-                                all_samples.push(amplified_sample.clamp(-1.0, 1.0));
-                            }
-                            user_data.buffer.extend(&all_samples);
-
-                            if user_data.state == State::Listening {
-                                let max_samples = user_data.pre_buffer_max_samples;
-                                if max_samples > 0 {
-                                    let current_len = user_data.buffer.len();
-                                    if current_len > max_samples {
-                                        let samples_to_remove = current_len - max_samples;
-                                        user_data.buffer.drain(..samples_to_remove);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        })
-        .register()?;
-
-    // Connect stream
-    let mut audio_info = spa::param::audio::AudioInfoRaw::new();
-    audio_info.set_format(spa::param::audio::AudioFormat::F32LE);
-    let obj = pw::spa::pod::Object {
-        type_: pw::spa::utils::SpaTypes::ObjectParamFormat.as_raw(),
-        id: pw::spa::param::ParamType::EnumFormat.as_raw(),
-        properties: audio_info.into(),
-    };
-    let values: Vec<u8> = pw::spa::pod::serialize::PodSerializer::serialize(
-        std::io::Cursor::new(Vec::new()),
-        &pw::spa::pod::Value::Object(obj),
-    )
-    .unwrap()
-    .0
-    .into_inner();
-
-    let mut params = [Pod::from_bytes(&values).unwrap()];
-
-    stream.connect(
-        spa::utils::Direction::Input,
-        None,
-        pw::stream::StreamFlags::AUTOCONNECT
-            | pw::stream::StreamFlags::MAP_BUFFERS
-            | pw::stream::StreamFlags::RT_PROCESS,
-        &mut params,
-    )?;
-
-    let ipc_data = data.clone();
+    config: CaptureConfig,
+    monitor: Arc<InputMonitor>,
+) -> Result<(), Box<dyn Error>> {
+    let (input, reader) = capture_channel(monitor.clone());
+    let engine = CaptureEngine::new(config, monitor);
     thread::spawn(move || {
-        handle_audio_commands(rx, ipc_data, app_tx);
+        run_recording_thread(engine, reader, rx, app_tx);
     });
 
-    mainloop.run();
-    Ok(())
-}
\ No newline at end of file
+    backend.run(input)
+}
@@ -0,0 +1,79 @@
+use crate::audio::capture::{CaptureBackend, CaptureFormat, CaptureInput};
+use hound::{SampleFormat, WavReader};
+use std::error::Error;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+const DEFAULT_BLOCK_FRAMES: usize = 1024;
+
+/// Replays a WAV file as if it were live capture. Used by tests and for
+/// developing without a sound server.
+pub struct WavFileCapture {
+    format: CaptureFormat,
+    samples: Vec<f32>,
+    block_frames: usize,
+    realtime: bool,
+}
+
+impl WavFileCapture {
+    pub fn open(path: &Path) -> Result<Self, hound::Error> {
+        let mut reader = WavReader::open(path)?;
+        let spec = reader.spec();
+        let samples = match spec.sample_format {
+            SampleFormat::Float => reader.samples::<f32>().collect::<Result<Vec<_>, _>>()?,
+            SampleFormat::Int => {
+                let scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|sample| sample.map(|s| s as f32 / scale))
+                    .collect::<Result<Vec<_>, _>>()?
+            }
+        };
+        Ok(Self {
+            format: CaptureFormat {
+                rate: spec.sample_rate,
+                channels: spec.channels as u32,
+            },
+            samples,
+            block_frames: DEFAULT_BLOCK_FRAMES,
+            realtime: false,
+        })
+    }
+
+    /// Number of frames handed to the engine per block.
+    pub fn block_frames(mut self, frames: usize) -> Self {
+        self.block_frames = frames.max(1);
+        self
+    }
+
+    /// Sleep for each block's duration instead of pushing the file as fast as possible.
+    pub fn realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+
+    pub fn format(&self) -> CaptureFormat {
+        self.format
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+}
+
+impl CaptureBackend for WavFileCapture {
+    fn run(self, mut input: CaptureInput) -> Result<(), Box<dyn Error>> {
+        input.set_format(self.format);
+        let block_len = self.block_frames * self.format.channels as usize;
+        let block_duration =
+            Duration::from_secs_f64(self.block_frames as f64 / self.format.rate as f64);
+        for block in self.samples.chunks(block_len) {
+            input.push_samples(block);
+            if self.realtime {
+                thread::sleep(block_duration);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// The setup page edits the input gain within this many dB either side of unity.
+pub const MAX_INPUT_GAIN_DB: f64 = 24.0;
+// The limiter keeps peaks under -0.3 dBFS.
+const LIMITER_CEILING: f32 = 0.966;
+const LIMITER_RELEASE_SECONDS: f64 = 0.1;
+// Soft clipping leaves everything below this level untouched.
+const SOFT_CLIP_KNEE: f32 = 0.75;
+
+/// What happens to captured audio that the input gain pushes past full scale.
+#[derive(Serialize, Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum InputClipMode {
+    /// Hard clips at full scale.
+    Clip,
+    /// Bends peaks smoothly towards full scale; quieter audio is untouched.
+    #[default]
+    SoftClip,
+    /// Turns the whole signal down just enough to keep peaks under full scale.
+    Limiter,
+    /// Keeps the float samples as they are, even above full scale.
+    Raw,
+}
+
+impl InputClipMode {
+    pub const ALL: [InputClipMode; 4] = [
+        InputClipMode::Clip,
+        InputClipMode::SoftClip,
+        InputClipMode::Limiter,
+        InputClipMode::Raw,
+    ];
+
+    pub fn index(self) -> usize {
+        Self::ALL.iter().position(|&mode| mode == self).unwrap_or(0)
+    }
+
+    /// Moves `steps` entries through `ALL`, stopping at either end.
+    pub fn step(self, steps: i32) -> Self {
+        let index = (self.index() as i32 + steps).clamp(0, Self::ALL.len() as i32 - 1);
+        Self::ALL[index as usize]
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            InputClipMode::Clip => "CLIP",
+            InputClipMode::SoftClip => "SOFT CLIP",
+            InputClipMode::Limiter => "LIMITER",
+            InputClipMode::Raw => "RAW",
+        }
+    }
+}
+
+/// Input gain followed by the clip mode, applied frame by frame.
+#[derive(Debug)]
+pub struct GainStage {
+    pub gain: f32,
+    pub mode: InputClipMode,
+    /// Limiter gain, at most 1.
+    envelope: f32,
+    release: f32,
+}
+
+impl GainStage {
+    pub fn new(gain: f32, mode: InputClipMode) -> Self {
+        Self {
+            gain,
+            mode,
+            envelope: 1.0,
+            release: 0.0,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        let dt = 1.0 / sample_rate.max(1) as f64;
+        self.release = (1.0 - (-dt / LIMITER_RELEASE_SECONDS).exp()) as f32;
+    }
+
+    /// Processes one interleaved frame and appends it to `out`.
+    pub fn process_frame(&mut self, frame: &[f32], out: &mut VecDeque<f32>) {
+        let gain = self.gain;
+        match self.mode {
+            InputClipMode::Clip => {
+                out.extend(frame.iter().map(|s| (s * gain).clamp(-1.0, 1.0)));
+            }
+            InputClipMode::SoftClip => {
+                out.extend(frame.iter().map(|s| soft_clip(s * gain)));
+            }
+            InputClipMode::Limiter => {
+                let peak = frame
+                    .iter()
+                    .fold(0.0_f32, |peak, s| peak.max((s * gain).abs()));
+                let needed = if peak > LIMITER_CEILING {
+                    LIMITER_CEILING / peak
+                } else {
+                    1.0
+                };
+                // Clamp down instantly and recover slowly, so no frame passes the ceiling.
+                self.envelope = (self.envelope + (1.0 - self.envelope) * self.release).min(needed);
+                let envelope = self.envelope;
+                out.extend(frame.iter().map(|s| s * gain * envelope));
+            }
+            InputClipMode::Raw => {
+                out.extend(frame.iter().map(|s| s * gain));
+            }
+        }
+    }
+}
+
+/// Linear up to the knee, then a tanh curve that meets full scale without a corner.
+fn soft_clip(sample: f32) -> f32 {
+    let magnitude = sample.abs();
+    if magnitude <= SOFT_CLIP_KNEE {
+        return sample;
+    }
+    let headroom = 1.0 - SOFT_CLIP_KNEE;
+    let bent = SOFT_CLIP_KNEE + headroom * ((magnitude - SOFT_CLIP_KNEE) / headroom).tanh();
+    bent.copysign(sample)
+}
@@ -0,0 +1,105 @@
+use crate::audio::meter::LevelMeter;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Each column of the scrolling waveform covers this much audio.
+pub const COLUMN_SECONDS: f64 = 0.01;
+// One display width of columns, about ten seconds.
+const COLUMN_COUNT: usize = 960;
+
+/// What the capture engine publishes for the display: the input level at all times, and
+/// while recording a rolling buffer of peaks, the elapsed time and whether anything clipped.
+/// Plain atomics, so the capture callback never waits on the UI.
+#[derive(Debug)]
+pub struct InputMonitor {
+    pub level: LevelMeter,
+    columns: Box<[AtomicU32]>,
+    /// Columns written since the recording started; the newest is at `written - 1`.
+    written: AtomicUsize,
+    recorded_frames: AtomicU64,
+    sample_rate: AtomicU32,
+    clipped: AtomicBool,
+    armed: AtomicBool,
+    /// Input blocks dropped because the recording thread fell behind.
+    overflows: AtomicU64,
+}
+
+impl Default for InputMonitor {
+    fn default() -> Self {
+        Self {
+            level: LevelMeter::default(),
+            columns: (0..COLUMN_COUNT).map(|_| AtomicU32::new(0)).collect(),
+            written: AtomicUsize::new(0),
+            recorded_frames: AtomicU64::new(0),
+            sample_rate: AtomicU32::new(0),
+            clipped: AtomicBool::new(false),
+            armed: AtomicBool::new(false),
+            overflows: AtomicU64::new(0),
+        }
+    }
+}
+
+impl InputMonitor {
+    /// Peaks of up to `count` of the latest columns, oldest first.
+    pub fn recent_peaks(&self, count: usize) -> Vec<f32> {
+        let written = self.written.load(Ordering::Acquire);
+        let count = count.min(written).min(COLUMN_COUNT);
+        (written - count..written)
+            .map(|i| f32::from_bits(self.columns[i % COLUMN_COUNT].load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    /// Time recorded so far, not counting the pre-roll.
+    pub fn elapsed(&self) -> Duration {
+        let rate = self.sample_rate.load(Ordering::Relaxed);
+        if rate == 0 {
+            return Duration::ZERO;
+        }
+        let frames = self.recorded_frames.load(Ordering::Relaxed);
+        Duration::from_secs_f64(frames as f64 / rate as f64)
+    }
+
+    /// Whether the current recording has hit full scale.
+    pub fn is_clipped(&self) -> bool {
+        self.clipped.load(Ordering::Relaxed)
+    }
+
+    /// Whether a recording is armed and still waiting for the input to cross the threshold.
+    pub fn is_armed(&self) -> bool {
+        self.armed.load(Ordering::Relaxed)
+    }
+
+    /// Input blocks lost since startup because the recording thread couldn't keep up.
+    pub fn overflows(&self) -> u64 {
+        self.overflows.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn start_recording(&self, sample_rate: u32) {
+        self.written.store(0, Ordering::Release);
+        self.recorded_frames.store(0, Ordering::Relaxed);
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+        self.clipped.store(false, Ordering::Relaxed);
+    }
+
+    pub(crate) fn push_column(&self, peak: f32) {
+        let index = self.written.load(Ordering::Relaxed);
+        self.columns[index % COLUMN_COUNT].store(peak.to_bits(), Ordering::Relaxed);
+        self.written.store(index + 1, Ordering::Release);
+    }
+
+    pub(crate) fn add_recorded_frames(&self, frames: u64) {
+        self.recorded_frames.fetch_add(frames, Ordering::Relaxed);
+    }
+
+    pub(crate) fn mark_clipped(&self) {
+        self.clipped.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_armed(&self, armed: bool) {
+        self.armed.store(armed, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_overflow(&self) {
+        self.overflows.fetch_add(1, Ordering::Relaxed);
+    }
+}
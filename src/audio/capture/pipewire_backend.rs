@@ -0,0 +1,101 @@
+use crate::audio::capture::{CaptureBackend, CaptureFormat, CaptureInput};
+use pipewire as pw;
+use pw::{properties::properties, spa};
+use spa::param::format::{MediaSubtype, MediaType};
+use spa::param::format_utils;
+use spa::pod::Pod;
+use std::convert::TryInto;
+use std::error::Error;
+use std::mem;
+
+/// Captures whatever the default sink is playing through a PipeWire capture stream.
+pub struct PipeWireCapture;
+
+impl CaptureBackend for PipeWireCapture {
+    fn run(self, input: CaptureInput) -> Result<(), Box<dyn Error>> {
+        pw::init();
+        let mainloop = pw::main_loop::MainLoopRc::new(None)?;
+        let context = pw::context::ContextRc::new(&mainloop, None)?;
+        let core = context.connect_rc(None)?;
+
+        let props = properties! {
+            *pw::keys::MEDIA_TYPE => "Audio",
+            *pw::keys::MEDIA_CATEGORY => "Capture",
+            *pw::keys::MEDIA_ROLE => "Music",
+            *pw::keys::STREAM_CAPTURE_SINK => "true",
+        };
+
+        let stream = pw::stream::StreamBox::new(&core, "audio-capture", props)?;
+
+        let _listener = stream
+            .add_local_listener_with_user_data(input)
+            .param_changed(|_, input, id, param| {
+                if let Some(param) = param {
+                    if id == pw::spa::param::ParamType::Format.as_raw() {
+                        if let Ok((MediaType::Audio, MediaSubtype::Raw)) =
+                            format_utils::parse_format(param)
+                        {
+                            let mut info = spa::param::audio::AudioInfoRaw::new();
+                            if info.parse(param).is_ok() {
+                                input.set_format(CaptureFormat {
+                                    rate: info.rate(),
+                                    channels: info.channels(),
+                                });
+                            }
+                        }
+                    }
+                }
+            })
+            .process(|stream, input| match stream.dequeue_buffer() {
+                None => {}
+                Some(mut buffer) => {
+                    let datas = buffer.datas_mut();
+                    if !datas.is_empty() {
+                        let data = &mut datas[0];
+                        let n_samples = data.chunk().size() / (mem::size_of::<f32>() as u32);
+                        if let Some(samples) = data.data() {
+                            // Decoded straight into the ring; the callback must not allocate.
+                            let len =
+                                (n_samples as usize * mem::size_of::<f32>()).min(samples.len());
+                            input.push(
+                                samples[..len]
+                                    .chunks_exact(mem::size_of::<f32>())
+                                    .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap())),
+                            );
+                        }
+                    }
+                }
+            })
+            .register()?;
+
+        // Connect stream
+        let mut audio_info = spa::param::audio::AudioInfoRaw::new();
+        audio_info.set_format(spa::param::audio::AudioFormat::F32LE);
+        let obj = pw::spa::pod::Object {
+            type_: pw::spa::utils::SpaTypes::ObjectParamFormat.as_raw(),
+            id: pw::spa::param::ParamType::EnumFormat.as_raw(),
+            properties: audio_info.into(),
+        };
+        let values: Vec<u8> = pw::spa::pod::serialize::PodSerializer::serialize(
+            std::io::Cursor::new(Vec::new()),
+            &pw::spa::pod::Value::Object(obj),
+        )
+        .unwrap()
+        .0
+        .into_inner();
+
+        let mut params = [Pod::from_bytes(&values).unwrap()];
+
+        stream.connect(
+            spa::utils::Direction::Input,
+            None,
+            pw::stream::StreamFlags::AUTOCONNECT
+                | pw::stream::StreamFlags::MAP_BUFFERS
+                | pw::stream::StreamFlags::RT_PROCESS,
+            &mut params,
+        )?;
+
+        mainloop.run();
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// Fixed-size single-producer, single-consumer queue of samples. Both ends only touch
+/// atomics, so the realtime side never blocks or allocates.
+#[derive(Debug)]
+struct Ring {
+    slots: Box<[AtomicU32]>,
+    /// Samples ever written and read; their difference is what's queued.
+    written: AtomicUsize,
+    read: AtomicUsize,
+}
+
+/// The writing end, owned by the capture callback.
+#[derive(Debug)]
+pub struct RingProducer {
+    ring: Arc<Ring>,
+}
+
+/// The reading end, owned by the thread that records.
+#[derive(Debug)]
+pub struct RingConsumer {
+    ring: Arc<Ring>,
+}
+
+/// Creates a ring holding up to `capacity` samples and returns its two ends.
+pub fn ring(capacity: usize) -> (RingProducer, RingConsumer) {
+    let ring = Arc::new(Ring {
+        slots: (0..capacity.max(1)).map(|_| AtomicU32::new(0)).collect(),
+        written: AtomicUsize::new(0),
+        read: AtomicUsize::new(0),
+    });
+    (RingProducer { ring: ring.clone() }, RingConsumer { ring })
+}
+
+impl RingProducer {
+    /// Queues all of `samples`, or none of them if they don't fit, so a dropped block
+    /// never leaves a partial frame behind. Returns whether they were queued.
+    pub fn push(&mut self, samples: impl ExactSizeIterator<Item = f32>) -> bool {
+        let ring = &self.ring;
+        let capacity = ring.slots.len();
+        let written = ring.written.load(Ordering::Relaxed);
+        let queued = written.wrapping_sub(ring.read.load(Ordering::Acquire));
+        let count = samples.len();
+        if capacity - queued < count {
+            return false;
+        }
+        for (i, sample) in samples.enumerate() {
+            ring.slots[written.wrapping_add(i) % capacity]
+                .store(sample.to_bits(), Ordering::Relaxed);
+        }
+        ring.written
+            .store(written.wrapping_add(count), Ordering::Release);
+        true
+    }
+}
+
+impl RingConsumer {
+    /// Moves up to `out.len()` queued samples into `out` and returns how many it moved.
+    pub fn pop_into(&mut self, out: &mut [f32]) -> usize {
+        let ring = &self.ring;
+        let capacity = ring.slots.len();
+        let read = ring.read.load(Ordering::Relaxed);
+        let queued = ring.written.load(Ordering::Acquire).wrapping_sub(read);
+        let count = queued.min(out.len());
+        for (i, slot) in out[..count].iter_mut().enumerate() {
+            *slot =
+                f32::from_bits(ring.slots[read.wrapping_add(i) % capacity].load(Ordering::Relaxed));
+        }
+        ring.read.store(read.wrapping_add(count), Ordering::Release);
+        count
+    }
+
+    /// Samples waiting to be read.
+    pub fn len(&self) -> usize {
+        let ring = &self.ring;
+        ring.written
+            .load(Ordering::Acquire)
+            .wrapping_sub(ring.read.load(Ordering::Relaxed))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
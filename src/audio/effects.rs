@@ -0,0 +1,157 @@
+use kira::{
+    Decibels, Mix, Tween,
+    effect::{
+        delay::{DelayBuilder, DelayHandle},
+        distortion::{DistortionBuilder, DistortionHandle},
+        filter::{FilterBuilder, FilterHandle, FilterMode},
+        reverb::{ReverbBuilder, ReverbHandle},
+    },
+    track::{TrackBuilder, TrackHandle},
+};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::time::Duration;
+
+pub const MIN_CUTOFF_HZ: f64 = 20.0;
+pub const MAX_CUTOFF_HZ: f64 = 20_000.0;
+pub const MAX_DRIVE_DB: f64 = 24.0;
+// kira sizes the delay line when the track is built, so the time is fixed.
+const DELAY_TIME: Duration = Duration::from_millis(375);
+const DELAY_FEEDBACK_DB: f32 = -6.0;
+
+/// Which filter, if any, a pad's audio passes through.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterKind {
+    #[default]
+    Off,
+    LowPass,
+    HighPass,
+}
+
+impl FilterKind {
+    pub const ALL: [FilterKind; 3] = [FilterKind::Off, FilterKind::LowPass, FilterKind::HighPass];
+
+    pub fn index(self) -> usize {
+        Self::ALL.iter().position(|&kind| kind == self).unwrap_or(0)
+    }
+
+    /// Moves `steps` entries through `ALL`, stopping at either end.
+    pub fn step(self, steps: i32) -> Self {
+        let index = (self.index() as i32 + steps).clamp(0, Self::ALL.len() as i32 - 1);
+        Self::ALL[index as usize]
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FilterKind::Off => "OFF",
+            FilterKind::LowPass => "LOW PASS",
+            FilterKind::HighPass => "HIGH PASS",
+        }
+    }
+}
+
+/// A pad's insert effects, applied in the order distortion, filter, delay, reverb. There are
+/// no sends: each pad runs its own chain, and the delay and reverb are mixed in place.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct PadEffects {
+    pub filter: FilterKind,
+    pub cutoff_hz: f64,
+    /// Distortion drive in dB; 0 leaves the audio clean.
+    pub drive_db: f64,
+    /// Wet share (0-1) of the delay and reverb; 0 bypasses them.
+    pub delay_mix: f64,
+    pub reverb_mix: f64,
+}
+
+impl Default for PadEffects {
+    fn default() -> Self {
+        Self {
+            filter: FilterKind::Off,
+            cutoff_hz: 1_000.0,
+            drive_db: 0.0,
+            delay_mix: 0.0,
+            reverb_mix: 0.0,
+        }
+    }
+}
+
+impl PadEffects {
+    /// True when every effect is off, so the pad needs no effect chain.
+    pub fn is_bypassed(&self) -> bool {
+        self.filter == FilterKind::Off
+            && self.drive_db <= 0.0
+            && self.delay_mix <= 0.0
+            && self.reverb_mix <= 0.0
+    }
+}
+
+/// A track holding a pad's effects, nested in the pad's track, and the effect handles.
+pub struct EffectChain {
+    track: TrackHandle,
+    distortion: DistortionHandle,
+    filter: FilterHandle,
+    delay: DelayHandle,
+    reverb: ReverbHandle,
+}
+
+impl EffectChain {
+    pub fn new(parent: &mut TrackHandle, effects: &PadEffects) -> Result<Self, Box<dyn Error>> {
+        let mut builder = TrackBuilder::new();
+        let distortion = builder.add_effect(DistortionBuilder::new().mix(Mix::DRY));
+        let filter = builder.add_effect(FilterBuilder::new().mix(Mix::DRY));
+        let delay = builder.add_effect(
+            DelayBuilder::new()
+                .delay_time(DELAY_TIME)
+                .feedback(Decibels(DELAY_FEEDBACK_DB))
+                .mix(Mix::DRY),
+        );
+        let reverb = builder.add_effect(ReverbBuilder::new().mix(Mix::DRY));
+        let track = parent
+            .add_sub_track(builder)
+            .map_err(|e| format!("failed to add effect track: {:?}", e))?;
+        let mut chain = Self {
+            track,
+            distortion,
+            filter,
+            delay,
+            reverb,
+        };
+        chain.apply(effects, Tween::default());
+        Ok(chain)
+    }
+
+    /// Moves every effect to `effects` over `tween`.
+    pub fn apply(&mut self, effects: &PadEffects, tween: Tween) {
+        let drive_mix = if effects.drive_db > 0.0 {
+            Mix::WET
+        } else {
+            Mix::DRY
+        };
+        self.distortion
+            .set_drive(Decibels(effects.drive_db as f32), tween);
+        self.distortion.set_mix(drive_mix, tween);
+
+        let filter_mix = match effects.filter {
+            FilterKind::Off => Mix::DRY,
+            FilterKind::LowPass => {
+                self.filter.set_mode(FilterMode::LowPass);
+                Mix::WET
+            }
+            FilterKind::HighPass => {
+                self.filter.set_mode(FilterMode::HighPass);
+                Mix::WET
+            }
+        };
+        self.filter.set_cutoff(effects.cutoff_hz, tween);
+        self.filter.set_mix(filter_mix, tween);
+
+        self.delay.set_mix(Mix(effects.delay_mix as f32), tween);
+        self.reverb.set_mix(Mix(effects.reverb_mix as f32), tween);
+    }
+
+    pub fn track_mut(&mut self) -> &mut TrackHandle {
+        &mut self.track
+    }
+}
@@ -0,0 +1,75 @@
+use kira::Frame;
+use serde::{Deserialize, Serialize};
+
+pub const MAX_ATTACK_MS: f64 = 2_000.0;
+pub const MAX_HOLD_MS: f64 = 10_000.0;
+pub const MAX_RELEASE_MS: f64 = 5_000.0;
+
+/// Attack/hold/release fades applied to a pad's trimmed region.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(default)]
+pub struct Envelope {
+    pub attack_ms: f64,
+    /// Time at full level after the attack. With a hold the release follows it, cutting the
+    /// region short; without one the release ends at the end point.
+    pub hold_ms: Option<f64>,
+    pub release_ms: f64,
+}
+
+impl Envelope {
+    /// True when the envelope leaves the audio untouched.
+    pub fn is_flat(&self) -> bool {
+        self.attack_ms <= 0.0 && self.release_ms <= 0.0 && self.hold_ms.is_none()
+    }
+
+    /// Seconds of a `length`-second region that actually sound.
+    pub fn sounding_length(&self, length: f64) -> f64 {
+        match self.hold_ms {
+            Some(hold_ms) => length.min((self.attack_ms + hold_ms + self.release_ms) / 1000.0),
+            None => length,
+        }
+    }
+
+    /// Second at which the release begins in a `length`-second region.
+    pub fn release_start(&self, length: f64) -> f64 {
+        let sounding = self.sounding_length(length);
+        (sounding - self.release_ms / 1000.0).max(0.0)
+    }
+
+    /// Gain (0-1) `t` seconds into playback of a `length`-second region.
+    pub fn gain_at(&self, t: f64, length: f64) -> f64 {
+        let attack = self.attack_ms / 1000.0;
+        let release = self.release_ms / 1000.0;
+        let sounding = self.sounding_length(length);
+        if t >= sounding {
+            return 0.0;
+        }
+        let attack_gain = if t < attack { t / attack } else { 1.0 };
+        let release_start = self.release_start(length);
+        let release_gain = if release > 0.0 && t > release_start {
+            1.0 - (t - release_start) / release
+        } else {
+            1.0
+        };
+        attack_gain.min(release_gain).clamp(0.0, 1.0)
+    }
+
+    /// Fades `frames` in playback order, which runs from the last frame when `reverse` is set.
+    /// A hold shortens the region to the attack, hold and release.
+    pub fn apply(&self, frames: &mut Vec<Frame>, sample_rate: u32, reverse: bool) {
+        if reverse {
+            frames.reverse();
+        }
+        let rate = sample_rate as f64;
+        let length = frames.len() as f64 / rate;
+        frames.truncate((self.sounding_length(length) * rate).round() as usize);
+        for (i, frame) in frames.iter_mut().enumerate() {
+            let gain = self.gain_at(i as f64 / rate, length) as f32;
+            frame.left *= gain;
+            frame.right *= gain;
+        }
+        if reverse {
+            frames.reverse();
+        }
+    }
+}
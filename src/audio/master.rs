@@ -0,0 +1,108 @@
+use kira::{
+    Frame,
+    effect::{Effect, EffectBuilder},
+    info::Info,
+};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+pub const MIN_MASTER_GAIN_DB: f64 = -60.0;
+pub const MAX_MASTER_GAIN_DB: f64 = 6.0;
+// Peaks never leave the master bus above this level (-0.3 dBFS).
+const CEILING: f32 = 0.966;
+const RELEASE_SECONDS: f64 = 0.1;
+// Master gain changes are ramped over this long so encoder steps don't click.
+const GAIN_SMOOTHING_SECONDS: f64 = 0.02;
+
+/// Master gain and limiter state shared between the app and the audio thread. Plain atomics,
+/// so the audio thread never waits on the UI.
+#[derive(Debug)]
+pub struct MasterBus {
+    gain_db: AtomicU32,
+    reduction_db: AtomicU32,
+}
+
+impl Default for MasterBus {
+    fn default() -> Self {
+        Self {
+            gain_db: AtomicU32::new(0.0_f32.to_bits()),
+            reduction_db: AtomicU32::new(0.0_f32.to_bits()),
+        }
+    }
+}
+
+impl MasterBus {
+    pub fn gain_db(&self) -> f64 {
+        f32::from_bits(self.gain_db.load(Ordering::Relaxed)) as f64
+    }
+
+    pub fn set_gain_db(&self, gain_db: f64) {
+        let gain_db = gain_db.clamp(MIN_MASTER_GAIN_DB, MAX_MASTER_GAIN_DB) as f32;
+        self.gain_db.store(gain_db.to_bits(), Ordering::Relaxed);
+    }
+
+    /// How far the limiter pulled the loudest frame of the last block down, in dB.
+    pub fn reduction_db(&self) -> f64 {
+        f32::from_bits(self.reduction_db.load(Ordering::Relaxed)) as f64
+    }
+}
+
+/// Builds the master gain and brickwall limiter for the main track.
+pub struct LimiterBuilder {
+    bus: Arc<MasterBus>,
+}
+
+impl LimiterBuilder {
+    pub fn new(bus: Arc<MasterBus>) -> Self {
+        Self { bus }
+    }
+}
+
+impl EffectBuilder for LimiterBuilder {
+    type Handle = Arc<MasterBus>;
+
+    fn build(self) -> (Box<dyn Effect>, Self::Handle) {
+        let limiter = Limiter {
+            bus: self.bus.clone(),
+            gain: db_to_amplitude(self.bus.gain_db() as f32),
+            envelope: 1.0,
+        };
+        (Box::new(limiter), self.bus)
+    }
+}
+
+struct Limiter {
+    bus: Arc<MasterBus>,
+    /// Master gain as an amplitude, ramping towards the bus setting.
+    gain: f32,
+    /// Limiter gain, at most 1.
+    envelope: f32,
+}
+
+impl Effect for Limiter {
+    fn process(&mut self, input: &mut [Frame], dt: f64, _info: &Info) {
+        let target = db_to_amplitude(self.bus.gain_db() as f32);
+        let smoothing = (1.0 - (-dt / GAIN_SMOOTHING_SECONDS).exp()) as f32;
+        let release = (1.0 - (-dt / RELEASE_SECONDS).exp()) as f32;
+        let mut deepest = 1.0_f32;
+        for frame in input.iter_mut() {
+            self.gain += (target - self.gain) * smoothing;
+            let left = frame.left * self.gain;
+            let right = frame.right * self.gain;
+            let peak = left.abs().max(right.abs());
+            let needed = if peak > CEILING { CEILING / peak } else { 1.0 };
+            // Clamp down instantly, recover slowly, and never let a frame over the ceiling.
+            self.envelope = (self.envelope + (1.0 - self.envelope) * release).min(needed);
+            *frame = Frame::new(left * self.envelope, right * self.envelope);
+            deepest = deepest.min(self.envelope);
+        }
+        let reduction_db = -20.0 * deepest.log10();
+        self.bus
+            .reduction_db
+            .store(reduction_db.to_bits(), Ordering::Relaxed);
+    }
+}
+
+fn db_to_amplitude(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
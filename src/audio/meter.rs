@@ -0,0 +1,116 @@
+use kira::{
+    Frame,
+    effect::{Effect, EffectBuilder},
+    info::Info,
+};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+// Peaks fall back at this rate once the signal drops.
+const PEAK_FALL_DB_PER_SECOND: f64 = 20.0;
+// Time constant of the RMS average.
+const RMS_WINDOW_SECONDS: f64 = 0.3;
+// Pad keys are MIDI notes, so this covers every pad.
+const PAD_METER_COUNT: usize = 128;
+
+/// Peak and RMS amplitude of one signal, written by the audio thread and read by the UI.
+#[derive(Debug, Default)]
+pub struct LevelMeter {
+    peak: AtomicU32,
+    rms: AtomicU32,
+}
+
+impl LevelMeter {
+    pub fn peak(&self) -> f32 {
+        f32::from_bits(self.peak.load(Ordering::Relaxed))
+    }
+
+    pub fn rms(&self) -> f32 {
+        f32::from_bits(self.rms.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, peak: f32, rms: f32) {
+        self.peak.store(peak.to_bits(), Ordering::Relaxed);
+        self.rms.store(rms.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Output meters of the master bus and of every pad, shared between the playback thread and
+/// the UI. Metering is per pad: the voices of a pad play on its track and share its meter.
+#[derive(Debug)]
+pub struct Meters {
+    pub master: Arc<LevelMeter>,
+    pads: Vec<Arc<LevelMeter>>,
+}
+
+impl Default for Meters {
+    fn default() -> Self {
+        Self {
+            master: Arc::default(),
+            pads: (0..PAD_METER_COUNT).map(|_| Arc::default()).collect(),
+        }
+    }
+}
+
+impl Meters {
+    pub fn pad(&self, key: u8) -> &Arc<LevelMeter> {
+        &self.pads[key as usize % PAD_METER_COUNT]
+    }
+}
+
+/// Builds a pass-through effect that measures whatever reaches it on a track.
+pub struct MeterBuilder {
+    meter: Arc<LevelMeter>,
+}
+
+impl MeterBuilder {
+    pub fn new(meter: Arc<LevelMeter>) -> Self {
+        Self { meter }
+    }
+}
+
+impl EffectBuilder for MeterBuilder {
+    type Handle = ();
+
+    fn build(self) -> (Box<dyn Effect>, Self::Handle) {
+        let meter = Meter {
+            meter: self.meter,
+            ballistics: Ballistics::default(),
+        };
+        (Box::new(meter), ())
+    }
+}
+
+struct Meter {
+    meter: Arc<LevelMeter>,
+    ballistics: Ballistics,
+}
+
+impl Effect for Meter {
+    fn process(&mut self, input: &mut [Frame], dt: f64, _info: &Info) {
+        let levels = input
+            .iter()
+            .map(|frame| frame.left.abs().max(frame.right.abs()));
+        self.ballistics.update(levels, dt, &self.meter);
+    }
+}
+
+/// Turns per-frame levels into a falling peak and a running RMS.
+#[derive(Debug, Default)]
+pub struct Ballistics {
+    peak: f32,
+    mean_square: f32,
+}
+
+impl Ballistics {
+    /// Feeds one block of frame levels, `dt` seconds apart, and publishes the result.
+    pub fn update(&mut self, levels: impl Iterator<Item = f32>, dt: f64, meter: &LevelMeter) {
+        let fall = 10.0_f64.powf(-PEAK_FALL_DB_PER_SECOND * dt / 20.0) as f32;
+        let smoothing = (1.0 - (-dt / RMS_WINDOW_SECONDS).exp()) as f32;
+        for level in levels {
+            self.peak = level.max(self.peak * fall);
+            self.mean_square += (level * level - self.mean_square) * smoothing;
+        }
+        meter.set(self.peak, self.mean_square.sqrt());
+    }
+}
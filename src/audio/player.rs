@@ -1,113 +1,353 @@
+pub mod offline;
 
+use crate::app::state::AppCommand;
+use crate::audio::effects::{EffectChain, PadEffects};
+use crate::audio::master::{LimiterBuilder, MasterBus};
+use crate::audio::meter::{MeterBuilder, Meters};
 use kira::{
     AudioManager, AudioManagerSettings, Easing, StartTime, Tween,
-    backend::DefaultBackend,
-    sound::static_sound::{StaticSoundData, StaticSoundHandle, StaticSoundSettings},
+    backend::{Backend, DefaultBackend},
+    sound::{
+        PlaybackState,
+        static_sound::{StaticSoundData, StaticSoundHandle, StaticSoundSettings},
+    },
+    track::{MainTrackBuilder, TrackBuilder, TrackHandle},
+};
+use log::error;
+use std::{
+    collections::{HashMap, VecDeque, hash_map::Entry},
+    error::Error,
+    sync::{
+        Arc,
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+    },
+    time::{Duration, Instant},
 };
-use log::{error, info};
-use std::{collections::HashMap, process::Command, sync::mpsc::Receiver, time::Duration};
-
-const LINK_APP_MIXER: &str = "alsa_playback.pushboard";
-const LINK_TARGET_MIXER: &str = "MyMixer";
-const LINK_APP_DEFAULT: &str = "alsa_playback.pushboard";
-const LINK_TARGET_DEFAULT: &str = "alsa_output.usb-Generic_USB_Audio-00.HiFi__Speaker__sink";
-
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub enum PlaybackSink {
-    Default,
-    Mixer,
-    Both,
-    None,
-}
+
+// How often finished sounds and playheads are reported back while no commands arrive.
+const FINISHED_POLL_INTERVAL: Duration = Duration::from_millis(20);
 
 #[derive(Debug)]
 pub struct KiraPlayRequest {
     pub pad_key: u8,
     pub sound_data: StaticSoundData,
     pub settings: StaticSoundSettings,
+    pub choke_group: Option<u8>,
+    /// How many voices of this pad may overlap before the oldest is stolen.
+    pub voices: u8,
+    pub effects: PadEffects,
+    /// Counts the pad's plays, so the app can tell a report about this play from a late one
+    /// about an earlier play.
+    pub generation: u64,
 }
 
 #[derive(Debug)]
 pub enum KiraCommand {
     Play(KiraPlayRequest),
     Stop(u8),
+    /// Stops every voice of every pad, e.g. before pad addresses change meaning.
+    StopAll,
     SetPlaybackRate(u8, f64),
     SetVolume(u8, f64),
+    /// Updates a pad's effects, live if it already has an effect track.
+    SetEffects(u8, PadEffects),
+}
+
+/// Plays pad sounds on any kira backend: the system output in the app, or an
+/// offline renderer in tests.
+pub struct Player<B: Backend> {
+    manager: AudioManager<B>,
+    active_handles: HashMap<u8, PadVoices>,
+    /// Each pad's own track, made on its first play.
+    pad_tracks: HashMap<u8, PadTrack>,
+    master: Arc<MasterBus>,
+    meters: Arc<Meters>,
+}
+
+/// A metered track for one pad, with the pad's effect chain nested in it once it needs one.
+struct PadTrack {
+    track: TrackHandle,
+    effects: Option<EffectChain>,
+}
+
+/// The voices of one pad, oldest first.
+struct PadVoices {
+    voices: VecDeque<Voice>,
+    choke_group: Option<u8>,
+    /// Generation of the newest play.
+    generation: u64,
 }
 
-pub fn run_kira_loop(rx: Receiver<KiraCommand>) -> Result<(), Box<dyn std::error::Error>> {
-    let mut manager = AudioManager::<DefaultBackend>::new(AudioManagerSettings::default())?;
-    let mut active_handles: HashMap<u8, StaticSoundHandle> = HashMap::new();
+struct Voice {
+    handle: StaticSoundHandle,
+    /// Length of the played sound, to turn a reversed voice's position into time played.
+    duration: f64,
+    reverse: bool,
+}
 
-    // update_pipewire_links(PlaybackSink::Mixer);
+impl Voice {
+    /// Seconds of the sound played so far in its playback direction. Read from the handle,
+    /// so rate changes mid-play are accounted for.
+    fn played(&self) -> f64 {
+        let position = self.handle.position();
+        if self.reverse {
+            (self.duration - position).max(0.0)
+        } else {
+            position
+        }
+    }
+}
 
-    for command in rx {
+impl PadVoices {
+    fn stop_all(&mut self) {
+        for voice in &mut self.voices {
+            let _ = voice.handle.stop(fast_tween());
+        }
+    }
+}
+
+impl<B: Backend> Player<B> {
+    /// Starts a manager whose main track ends in the master gain, limiter and output meter,
+    /// so every pad and effect track goes through them.
+    pub fn new(
+        backend_settings: B::Settings,
+        master: Arc<MasterBus>,
+        meters: Arc<Meters>,
+    ) -> Result<Self, Box<dyn Error>>
+    where
+        B::Settings: Default,
+        B::Error: std::fmt::Debug,
+    {
+        let manager = AudioManager::<B>::new(AudioManagerSettings {
+            backend_settings,
+            main_track_builder: MainTrackBuilder::new()
+                .with_effect(LimiterBuilder::new(master.clone()))
+                .with_effect(MeterBuilder::new(meters.master.clone())),
+            ..Default::default()
+        })
+        .map_err(|e| format!("failed to start audio: {:?}", e))?;
+        Ok(Self {
+            manager,
+            active_handles: HashMap::new(),
+            pad_tracks: HashMap::new(),
+            master,
+            meters,
+        })
+    }
+
+    pub fn handle_command(&mut self, command: KiraCommand) {
         match command {
             KiraCommand::Play(req) => {
-                stop_sound_if_playing(&mut active_handles, req.pad_key);
-                match manager.play(req.sound_data.with_settings(req.settings)) {
+                if let Some(group) = req.choke_group {
+                    self.choke(group, req.pad_key);
+                }
+                let pad = self
+                    .active_handles
+                    .entry(req.pad_key)
+                    .or_insert_with(|| PadVoices {
+                        voices: VecDeque::new(),
+                        choke_group: req.choke_group,
+                        generation: req.generation,
+                    });
+                pad.choke_group = req.choke_group;
+                pad.generation = req.generation;
+                // Steal the oldest voices so the new one fits
+                while pad.voices.len() >= req.voices.max(1) as usize {
+                    if let Some(mut oldest) = pad.voices.pop_front() {
+                        let _ = oldest.handle.stop(fast_tween());
+                    }
+                }
+                let duration = req.sound_data.duration().as_secs_f64();
+                let reverse = req.settings.reverse;
+                let sound = req.sound_data.with_settings(req.settings);
+                let played = match self.pad_track(req.pad_key, &req.effects) {
+                    Ok(track) => track.play(sound).map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                match played {
                     Ok(handle) => {
-                        active_handles.insert(req.pad_key, handle);
+                        if let Some(pad) = self.active_handles.get_mut(&req.pad_key) {
+                            pad.voices.push_back(Voice {
+                                handle,
+                                duration,
+                                reverse,
+                            });
+                        }
                     }
                     Err(e) => error!("Failed to play: {}", e),
                 }
             }
             KiraCommand::Stop(key) => {
-                stop_sound_if_playing(&mut active_handles, key);
+                // Keep the handles so the stop is reported by `take_finished` once the fade ends.
+                if let Some(pad) = self.active_handles.get_mut(&key) {
+                    pad.stop_all();
+                }
+            }
+            KiraCommand::StopAll => {
+                for pad in self.active_handles.values_mut() {
+                    pad.stop_all();
+                }
             }
             KiraCommand::SetPlaybackRate(key, rate) => {
-                if let Some(handle) = active_handles.get_mut(&key) {
-                    let _ = handle.set_playback_rate(rate, fast_tween());
+                if let Some(pad) = self.active_handles.get_mut(&key) {
+                    for voice in &mut pad.voices {
+                        let _ = voice.handle.set_playback_rate(rate, fast_tween());
+                    }
                 }
             }
             KiraCommand::SetVolume(key, vol) => {
-                if let Some(handle) = active_handles.get_mut(&key) {
-                    let _ = handle.set_volume(vol as f32, fast_tween());
+                if let Some(pad) = self.active_handles.get_mut(&key) {
+                    for voice in &mut pad.voices {
+                        let _ = voice.handle.set_volume(vol as f32, fast_tween());
+                    }
+                }
+            }
+            KiraCommand::SetEffects(key, effects) => {
+                // Pads without an effect chain get one on their next play.
+                if let Some(chain) = self
+                    .pad_tracks
+                    .get_mut(&key)
+                    .and_then(|pad| pad.effects.as_mut())
+                {
+                    chain.apply(&effects, fast_tween());
                 }
             }
         }
     }
-    Ok(())
-}
 
-pub fn update_pipewire_links(sink: PlaybackSink) {
-    let run_link = |connect: bool, output: &str, input: &str| {
-        let mut cmd = Command::new("pw-link");
-        if !connect {
-            cmd.arg("-d");
-        }
-        cmd.arg(output).arg(input);
-        // Ignore errors as links might already exist/not exist
-        let _ = cmd.output();
-    };
-
-    match sink {
-        PlaybackSink::Default => {
-            run_link(true, LINK_APP_DEFAULT, LINK_TARGET_DEFAULT);
-            run_link(false, LINK_APP_MIXER, LINK_TARGET_MIXER);
-            info!("Audio Routing: Default Speaker Only");
-        }
-        PlaybackSink::Mixer => {
-            run_link(false, LINK_APP_DEFAULT, LINK_TARGET_DEFAULT);
-            run_link(true, LINK_APP_MIXER, LINK_TARGET_MIXER);
-            info!("Audio Routing: MyMixer Only");
+    /// The track the pad's next voice plays on, with its effects set to `effects`. The effect
+    /// chain is only made once the pad uses an effect; after that the pad keeps it so tails
+    /// aren't cut when effects are switched off.
+    fn pad_track(
+        &mut self,
+        key: u8,
+        effects: &PadEffects,
+    ) -> Result<&mut TrackHandle, Box<dyn Error>> {
+        let pad = match self.pad_tracks.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let mut builder = TrackBuilder::new();
+                builder.add_effect(MeterBuilder::new(self.meters.pad(key).clone()));
+                let track = self
+                    .manager
+                    .add_sub_track(builder)
+                    .map_err(|e| format!("failed to add track for pad {}: {:?}", key, e))?;
+                entry.insert(PadTrack {
+                    track,
+                    effects: None,
+                })
+            }
+        };
+        if pad.effects.is_none() && !effects.is_bypassed() {
+            match EffectChain::new(&mut pad.track, effects) {
+                Ok(chain) => pad.effects = Some(chain),
+                Err(e) => error!("Playing pad {} without effects: {}", key, e),
+            }
         }
-        PlaybackSink::Both => {
-            run_link(true, LINK_APP_DEFAULT, LINK_TARGET_DEFAULT);
-            run_link(true, LINK_APP_MIXER, LINK_TARGET_MIXER);
-            info!("Audio Routing: Both");
+        match &mut pad.effects {
+            Some(chain) => {
+                chain.apply(effects, fast_tween());
+                Ok(chain.track_mut())
+            }
+            None => Ok(&mut pad.track),
         }
-        PlaybackSink::None => {
-            run_link(false, LINK_APP_DEFAULT, LINK_TARGET_DEFAULT);
-            run_link(false, LINK_APP_MIXER, LINK_TARGET_MIXER);
-            info!("Audio Routing: Muted (None)");
+    }
+
+    /// Fades out every pad in `group` except `pad_key`, whose voices are limited by stealing instead.
+    /// They are reported by `take_finished` like any other stop.
+    fn choke(&mut self, group: u8, pad_key: u8) {
+        for (&key, pad) in &mut self.active_handles {
+            if key != pad_key && pad.choke_group == Some(group) {
+                pad.stop_all();
+            }
         }
     }
+
+    pub fn is_playing(&self, key: u8) -> bool {
+        self.active_handles.get(&key).is_some_and(|pad| {
+            pad.voices
+                .iter()
+                .any(|voice| voice.handle.state() != PlaybackState::Stopped)
+        })
+    }
+
+    /// Forgets voices that have stopped and returns the pads that went silent, with the
+    /// generation of their last play.
+    pub fn take_finished(&mut self) -> Vec<(u8, u64)> {
+        let mut finished = Vec::new();
+        self.active_handles.retain(|&key, pad| {
+            pad.voices
+                .retain(|voice| voice.handle.state() != PlaybackState::Stopped);
+            if pad.voices.is_empty() {
+                finished.push((key, pad.generation));
+                return false;
+            }
+            true
+        });
+        finished
+    }
+
+    /// Seconds played of each pad's newest voice.
+    pub fn playheads(&self) -> Vec<(u8, f64)> {
+        self.active_handles
+            .iter()
+            .filter_map(|(&key, pad)| Some((key, pad.voices.back()?.played())))
+            .collect()
+    }
+
+    pub fn master(&self) -> &Arc<MasterBus> {
+        &self.master
+    }
+
+    pub fn meters(&self) -> &Arc<Meters> {
+        &self.meters
+    }
+
+    pub fn manager_mut(&mut self) -> &mut AudioManager<B> {
+        &mut self.manager
+    }
+}
+
+pub fn run_kira_loop(
+    rx: Receiver<KiraCommand>,
+    app_tx: Sender<AppCommand>,
+    master: Arc<MasterBus>,
+    meters: Arc<Meters>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let player = Player::<DefaultBackend>::new(Default::default(), master, meters)?;
+    run_player(player, rx, app_tx);
+    Ok(())
 }
 
-fn stop_sound_if_playing(handles: &mut HashMap<u8, StaticSoundHandle>, key: u8) {
-    if let Some(mut handle) = handles.remove(&key) {
-        let _ = handle.stop(fast_tween());
+pub fn run_player<B: Backend>(
+    mut player: Player<B>,
+    rx: Receiver<KiraCommand>,
+    app_tx: Sender<AppCommand>,
+) {
+    let mut last_report = Instant::now();
+    loop {
+        match rx.recv_timeout(FINISHED_POLL_INTERVAL) {
+            Ok(command) => player.handle_command(command),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        for (key, generation) in player.take_finished() {
+            if app_tx
+                .send(AppCommand::PlaybackFinished(key, generation))
+                .is_err()
+            {
+                return;
+            }
+        }
+        // Bursts of commands shouldn't flood the app with positions.
+        if last_report.elapsed() >= FINISHED_POLL_INTERVAL {
+            last_report = Instant::now();
+            for (key, played) in player.playheads() {
+                if app_tx.send(AppCommand::Playhead(key, played)).is_err() {
+                    return;
+                }
+            }
+        }
     }
 }
 
@@ -117,4 +357,4 @@ fn fast_tween() -> Tween {
         duration: Duration::from_millis(10),
         easing: Easing::Linear,
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,33 @@
+use crate::audio::master::MasterBus;
+use crate::audio::meter::Meters;
+use crate::audio::player::Player;
+use kira::backend::mock::{MockBackend, MockBackendSettings};
+use std::error::Error;
+use std::sync::Arc;
+
+const OUTPUT_CHANNELS: u16 = 2;
+// Matches kira's default internal buffer so commands land on the same block boundaries.
+const RENDER_BLOCK_FRAMES: usize = 128;
+
+impl Player<MockBackend> {
+    /// A player that only produces audio when `render` is called.
+    pub fn offline(sample_rate: u32) -> Result<Self, Box<dyn Error>> {
+        Self::new(
+            MockBackendSettings { sample_rate },
+            Arc::new(MasterBus::default()),
+            Arc::new(Meters::default()),
+        )
+    }
+
+    /// Mixes the next `frames` frames of output and returns them interleaved (L, R).
+    /// Commands handled before this call take effect at the start of the render.
+    pub fn render(&mut self, frames: usize) -> Vec<f32> {
+        let mut output = vec![0.0; frames * OUTPUT_CHANNELS as usize];
+        let backend = self.manager_mut().backend_mut();
+        for block in output.chunks_mut(RENDER_BLOCK_FRAMES * OUTPUT_CHANNELS as usize) {
+            backend.on_start_processing();
+            backend.process(block, OUTPUT_CHANNELS);
+        }
+        output
+    }
+}
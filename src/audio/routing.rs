@@ -0,0 +1,439 @@
+use crate::app::state::AppCommand;
+use crate::config::RoutingConfig;
+use log::{error, info, warn};
+use pipewire as pw;
+use pw::properties::properties;
+use pw::proxy::ProxyT;
+use pw::registry::GlobalObject;
+use pw::spa::utils::dict::DictRef;
+use pw::types::ObjectType;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::mpsc::Sender;
+
+const LINK_FACTORY: &str = "link-factory";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PlaybackSink {
+    Default,
+    Mixer,
+    Both,
+    None,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RoutingStatus {
+    Connected(PlaybackSink),
+    /// Nodes that the requested routing needs but that are not on the graph right now.
+    /// Their links are restored as soon as they reappear.
+    Missing(Vec<String>),
+    Failed(String),
+}
+
+struct Port {
+    node: u32,
+    is_output: bool,
+    channel: Option<String>,
+}
+
+/// An output port and the input port it feeds.
+pub type PortPair = (u32, u32);
+
+/// The nodes, ports and links on the graph, plus the links that failed to be created.
+/// A failed link is only tried again once one of its nodes changes.
+#[derive(Default)]
+pub struct Graph {
+    nodes: HashMap<u32, String>,
+    ports: HashMap<u32, Port>,
+    links: HashMap<u32, PortPair>,
+    /// Links that failed, with the reason.
+    failed: HashMap<PortPair, String>,
+}
+
+/// What it takes to bring the graph in line with the wanted targets.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct LinkPlan {
+    pub create: Vec<PortPair>,
+    pub destroy: Vec<u32>,
+    /// Wanted nodes that aren't on the graph.
+    pub missing: Vec<String>,
+    /// Some wanted links don't exist yet, or their ports haven't been announced.
+    pub waiting: bool,
+    /// Why a wanted link failed, if one did. It isn't retried until its nodes change.
+    pub failed: Option<String>,
+}
+
+impl Graph {
+    pub fn add_node(&mut self, id: u32, name: &str) {
+        self.nodes.insert(id, name.to_string());
+        self.clear_failures(id);
+    }
+
+    pub fn add_port(&mut self, id: u32, node: u32, is_output: bool, channel: Option<&str>) {
+        let port = Port {
+            node,
+            is_output,
+            channel: channel.map(str::to_string),
+        };
+        self.ports.insert(id, port);
+        self.clear_failures(node);
+    }
+
+    pub fn add_link(&mut self, id: u32, pair: PortPair) {
+        self.links.insert(id, pair);
+    }
+
+    /// Forgets a node, port or link. Returns whether the id was known.
+    pub fn remove(&mut self, id: u32) -> bool {
+        if self.nodes.remove(&id).is_some() {
+            self.clear_failures(id);
+            return true;
+        }
+        if let Some(port) = self.ports.remove(&id) {
+            self.clear_failures(port.node);
+            return true;
+        }
+        self.links.remove(&id).is_some()
+    }
+
+    /// Keeps `pair` out of future plans until one of its nodes changes.
+    pub fn mark_failed(&mut self, pair: PortPair, reason: String) {
+        self.failed.insert(pair, reason);
+    }
+
+    fn clear_failures(&mut self, node: u32) {
+        let ports = &self.ports;
+        let on_node = |port: &u32| ports.get(port).is_some_and(|port| port.node == node);
+        self.failed
+            .retain(|(output, input), _| !on_node(output) && !on_node(input));
+    }
+
+    fn node_id(&self, name: &str) -> Option<u32> {
+        self.nodes
+            .iter()
+            .find(|(_, node_name)| node_name.as_str() == name)
+            .map(|(id, _)| *id)
+    }
+
+    fn ports_of(&self, node: u32, is_output: bool) -> Vec<(u32, Option<&str>)> {
+        let mut ports: Vec<_> = self
+            .ports
+            .iter()
+            .filter(|(_, port)| port.node == node && port.is_output == is_output)
+            .map(|(id, port)| (*id, port.channel.as_deref()))
+            .collect();
+        ports.sort_by_key(|(id, _)| *id);
+        ports
+    }
+
+    /// Pairs the app's output ports with the target's input ports by channel name,
+    /// falling back to port order when channels are not labelled.
+    fn port_pairs(&self, app: u32, target: u32) -> Vec<PortPair> {
+        let outputs = self.ports_of(app, true);
+        let inputs = self.ports_of(target, false);
+        outputs
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (out_id, channel))| {
+                let by_channel = channel.and_then(|channel| {
+                    inputs
+                        .iter()
+                        .find(|(_, in_channel)| *in_channel == Some(channel))
+                });
+                by_channel
+                    .or_else(|| inputs.get(index))
+                    .map(|(in_id, _)| (*out_id, *in_id))
+            })
+            .collect()
+    }
+
+    /// Works out which links from `app_node` to each target should be created or destroyed,
+    /// given whether each target is wanted.
+    pub fn plan(&self, app_node: &str, targets: &[(String, bool)]) -> LinkPlan {
+        let mut plan = LinkPlan::default();
+        let app = self.node_id(app_node);
+        let linked: HashSet<PortPair> = self.links.values().copied().collect();
+
+        for (target_name, wanted) in targets {
+            let target = self.node_id(target_name);
+            let (Some(app), Some(target)) = (app, target) else {
+                if *wanted {
+                    if app.is_none() && !plan.missing.iter().any(|name| name == app_node) {
+                        plan.missing.push(app_node.to_string());
+                    }
+                    if target.is_none() {
+                        plan.missing.push(target_name.clone());
+                    }
+                }
+                continue;
+            };
+
+            let pairs = self.port_pairs(app, target);
+            if !wanted {
+                plan.destroy.extend(
+                    self.links
+                        .iter()
+                        .filter(|(_, pair)| pairs.contains(pair))
+                        .map(|(id, _)| *id),
+                );
+                continue;
+            }
+
+            if pairs.is_empty() {
+                // The node exists but has not announced its ports yet.
+                plan.waiting = true;
+                continue;
+            }
+            for pair in pairs {
+                if linked.contains(&pair) {
+                    continue;
+                }
+                if let Some(reason) = self.failed.get(&pair) {
+                    plan.failed = plan.failed.or_else(|| Some(reason.clone()));
+                } else {
+                    plan.waiting = true;
+                    plan.create.push(pair);
+                }
+            }
+        }
+        plan.destroy.sort_unstable();
+        plan
+    }
+}
+
+struct Router {
+    config: RoutingConfig,
+    app_tx: Sender<AppCommand>,
+    sink: Option<PlaybackSink>,
+    graph: Graph,
+    // Link proxies are kept until the server announces the link or rejects it.
+    pending: HashMap<PortPair, pw::link::Link>,
+    last_status: Option<RoutingStatus>,
+}
+
+impl Router {
+    fn new(config: RoutingConfig, app_tx: Sender<AppCommand>) -> Self {
+        Self {
+            config,
+            app_tx,
+            sink: None,
+            graph: Graph::default(),
+            pending: HashMap::new(),
+            last_status: None,
+        }
+    }
+
+    /// Records a node, port or link. Returns true if the graph changed in a way that matters.
+    fn track_global(&mut self, global: &GlobalObject<&DictRef>) -> bool {
+        let Some(props) = global.props else {
+            return false;
+        };
+        match global.type_ {
+            ObjectType::Node => {
+                let Some(name) = props.get(*pw::keys::NODE_NAME) else {
+                    return false;
+                };
+                self.graph.add_node(global.id, name);
+                true
+            }
+            ObjectType::Port => {
+                let node = props.get(*pw::keys::NODE_ID).and_then(|id| id.parse().ok());
+                let Some(node) = node else {
+                    return false;
+                };
+                self.graph.add_port(
+                    global.id,
+                    node,
+                    props.get(*pw::keys::PORT_DIRECTION) == Some("out"),
+                    props.get(*pw::keys::AUDIO_CHANNEL),
+                );
+                true
+            }
+            ObjectType::Link => {
+                let port = |key: &str| props.get(key).and_then(|id| id.parse().ok());
+                let (Some(output), Some(input)) = (
+                    port(*pw::keys::LINK_OUTPUT_PORT),
+                    port(*pw::keys::LINK_INPUT_PORT),
+                ) else {
+                    return false;
+                };
+                self.pending.remove(&(output, input));
+                self.graph.add_link(global.id, (output, input));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn forget_global(&mut self, id: u32) -> bool {
+        self.graph.remove(id)
+    }
+
+    fn on_error(&mut self, proxy_id: u32, message: &str) {
+        let failed = self
+            .pending
+            .iter()
+            .find(|(_, link)| link.upcast_ref().id() == proxy_id)
+            .map(|(pair, _)| *pair);
+        if let Some(pair) = failed {
+            self.pending.remove(&pair);
+            let reason = format!("link {} -> {} failed: {}", pair.0, pair.1, message);
+            self.graph.mark_failed(pair, reason.clone());
+            self.report(RoutingStatus::Failed(reason));
+        }
+    }
+
+    fn targets(&self, sink: PlaybackSink) -> [(String, bool); 2] {
+        let (speaker, mixer) = match sink {
+            PlaybackSink::Default => (true, false),
+            PlaybackSink::Mixer => (false, true),
+            PlaybackSink::Both => (true, true),
+            PlaybackSink::None => (false, false),
+        };
+        [
+            (self.config.default_target.clone(), speaker),
+            (self.config.mixer_target.clone(), mixer),
+        ]
+    }
+
+    /// Creates or destroys links until the graph matches the requested sink.
+    fn reconcile(&mut self, core: &pw::core::Core, registry: &pw::registry::Registry) {
+        let Some(sink) = self.sink else {
+            return;
+        };
+        let plan = self
+            .graph
+            .plan(&self.config.playback_node, &self.targets(sink));
+        for link_id in plan.destroy {
+            let _ = registry.destroy_global(link_id);
+        }
+        let mut failed = plan.failed;
+        for pair in plan.create {
+            if self.pending.contains_key(&pair) {
+                continue;
+            }
+            match create_link(core, pair) {
+                Ok(link) => {
+                    self.pending.insert(pair, link);
+                }
+                Err(e) => {
+                    let reason = format!("cannot link {} -> {}: {}", pair.0, pair.1, e);
+                    self.graph.mark_failed(pair, reason.clone());
+                    failed = failed.or(Some(reason));
+                }
+            }
+        }
+
+        if !plan.missing.is_empty() {
+            self.report(RoutingStatus::Missing(plan.missing));
+        } else if let Some(reason) = failed {
+            self.report(RoutingStatus::Failed(reason));
+        } else if !plan.waiting {
+            self.report(RoutingStatus::Connected(sink));
+        }
+    }
+
+    fn report(&mut self, status: RoutingStatus) {
+        if self.last_status.as_ref() == Some(&status) {
+            return;
+        }
+        match &status {
+            RoutingStatus::Connected(sink) => info!("Audio Routing: {:?}", sink),
+            RoutingStatus::Missing(nodes) => warn!("Audio Routing: waiting for {:?}", nodes),
+            RoutingStatus::Failed(reason) => error!("Audio Routing: {}", reason),
+        }
+        self.last_status = Some(status.clone());
+        let _ = self.app_tx.send(AppCommand::RoutingStatus(status));
+    }
+}
+
+fn create_link(
+    core: &pw::core::Core,
+    (output, input): PortPair,
+) -> Result<pw::link::Link, pw::Error> {
+    core.create_object::<pw::link::Link>(
+        LINK_FACTORY,
+        &properties! {
+            *pw::keys::LINK_OUTPUT_PORT => output.to_string(),
+            *pw::keys::LINK_INPUT_PORT => input.to_string(),
+            // Keep the link alive on the server after our proxy goes away.
+            *pw::keys::OBJECT_LINGER => "true",
+        },
+    )
+}
+
+/// Watches the PipeWire graph and keeps pushboard's playback node linked to the
+/// targets selected by the most recent `PlaybackSink` received on `rx`.
+pub fn run_routing_loop(
+    rx: pw::channel::Receiver<PlaybackSink>,
+    app_tx: Sender<AppCommand>,
+    config: RoutingConfig,
+) -> Result<(), pw::Error> {
+    pw::init();
+    let mainloop = pw::main_loop::MainLoopRc::new(None)?;
+    let context = pw::context::ContextRc::new(&mainloop, None)?;
+    let core = context.connect_rc(None)?;
+    let registry = core.get_registry_rc()?;
+    let router = Rc::new(RefCell::new(Router::new(config, app_tx)));
+
+    let _registry_listener = registry
+        .add_listener_local()
+        .global({
+            let router = router.clone();
+            let core = core.clone();
+            let registry = registry.downgrade();
+            move |global| {
+                let Some(registry) = registry.upgrade() else {
+                    return;
+                };
+                let mut router = router.borrow_mut();
+                if router.track_global(global) {
+                    router.reconcile(&core, &registry);
+                }
+            }
+        })
+        .global_remove({
+            let router = router.clone();
+            let core = core.clone();
+            let registry = registry.downgrade();
+            move |id| {
+                let Some(registry) = registry.upgrade() else {
+                    return;
+                };
+                let mut router = router.borrow_mut();
+                if router.forget_global(id) {
+                    router.reconcile(&core, &registry);
+                }
+            }
+        })
+        .register();
+
+    let _core_listener = core
+        .add_listener_local()
+        .error({
+            let router = router.clone();
+            move |id, _seq, _res, message| {
+                router.borrow_mut().on_error(id, message);
+            }
+        })
+        .register();
+
+    let _receiver = rx.attach(mainloop.loop_(), {
+        let router = router.clone();
+        let core = core.clone();
+        let registry = registry.downgrade();
+        move |sink| {
+            let Some(registry) = registry.upgrade() else {
+                return;
+            };
+            let mut router = router.borrow_mut();
+            router.sink = Some(sink);
+            router.last_status = None;
+            router.reconcile(&core, &registry);
+        }
+    });
+
+    mainloop.run();
+    Ok(())
+}
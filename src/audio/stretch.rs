@@ -0,0 +1,137 @@
+use kira::Frame;
+use kira::sound::static_sound::StaticSoundData;
+use std::sync::Arc;
+
+// Grains of ~21 ms at 48 kHz, overlapped by half.
+const GRAIN_FRAMES: usize = 1024;
+const HOP_FRAMES: usize = GRAIN_FRAMES / 2;
+// How far a grain may move from its nominal position to line up with the previous one.
+const SEARCH_FRAMES: isize = 256;
+
+/// What a stretched render depends on; a cached render is reused while these match.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StretchParams {
+    pub start_sec: f64,
+    pub end_sec: f64,
+    pub semitones: f64,
+    /// Speed factor: 2.0 plays twice as fast at the same pitch.
+    pub tempo: f64,
+}
+
+/// Renders the trimmed region of `data` with pitch and tempo changed independently.
+pub fn render(data: &StaticSoundData, params: &StretchParams) -> StaticSoundData {
+    StaticSoundData {
+        sample_rate: data.sample_rate,
+        frames: Arc::from(render_frames(data, params)),
+        settings: Default::default(),
+        slice: None,
+    }
+}
+
+/// Like `render`, but returns the frames so they can be processed further.
+pub fn render_frames(data: &StaticSoundData, params: &StretchParams) -> Vec<Frame> {
+    let sample_rate = data.sample_rate as f64;
+    let first = ((params.start_sec * sample_rate) as usize).min(data.frames.len());
+    let last = ((params.end_sec * sample_rate) as usize).clamp(first, data.frames.len());
+    let region = &data.frames[first..last];
+
+    let pitch_ratio = 2.0_f64.powf(params.semitones / 12.0);
+    // Stretch so that resampling by the pitch ratio lands on the wanted length.
+    let stretched = time_stretch(region, pitch_ratio / params.tempo);
+    resample(&stretched, pitch_ratio)
+}
+
+/// WSOLA: overlap-adds windowed grains of the input at `ratio` times its length, nudging
+/// each grain to where it best continues the previous one so the pitch is preserved.
+pub fn time_stretch(input: &[Frame], ratio: f64) -> Vec<Frame> {
+    if input.is_empty() || (ratio - 1.0).abs() < 1e-6 {
+        return input.to_vec();
+    }
+    let output_len = (input.len() as f64 * ratio).round() as usize;
+    let window: Vec<f32> = (0..GRAIN_FRAMES)
+        .map(|i| {
+            let phase = i as f32 / GRAIN_FRAMES as f32;
+            0.5 - 0.5 * (std::f32::consts::TAU * phase).cos()
+        })
+        .collect();
+
+    let mut output = vec![Frame::ZERO; output_len + GRAIN_FRAMES];
+    let mut weights = vec![0.0_f32; output_len + GRAIN_FRAMES];
+    let mut previous_grain = 0_isize;
+    let mut output_pos = 0;
+    while output_pos < output_len {
+        let nominal = (output_pos as f64 / ratio) as isize;
+        let grain = if output_pos == 0 {
+            0
+        } else {
+            best_grain_start(input, previous_grain + HOP_FRAMES as isize, nominal)
+        };
+        for (i, &w) in window.iter().enumerate() {
+            let frame = frame_at(input, grain + i as isize);
+            let out = &mut output[output_pos + i];
+            out.left += frame.left * w;
+            out.right += frame.right * w;
+            weights[output_pos + i] += w;
+        }
+        previous_grain = grain;
+        output_pos += HOP_FRAMES;
+    }
+
+    output.truncate(output_len);
+    for (frame, &weight) in output.iter_mut().zip(&weights) {
+        if weight > 1e-3 {
+            frame.left /= weight;
+            frame.right /= weight;
+        }
+    }
+    output
+}
+
+/// Linear-interpolation resampler; `rate` above 1 raises pitch and shortens the audio.
+pub fn resample(input: &[Frame], rate: f64) -> Vec<Frame> {
+    if input.is_empty() || (rate - 1.0).abs() < 1e-6 {
+        return input.to_vec();
+    }
+    let output_len = (input.len() as f64 / rate).floor() as usize;
+    (0..output_len)
+        .map(|i| {
+            let position = i as f64 * rate;
+            let index = position as isize;
+            let fraction = (position - index as f64) as f32;
+            let a = frame_at(input, index);
+            let b = frame_at(input, index + 1);
+            Frame::new(
+                a.left + (b.left - a.left) * fraction,
+                a.right + (b.right - a.right) * fraction,
+            )
+        })
+        .collect()
+}
+
+/// The grain start near `nominal` whose first half best matches the audio that followed
+/// the previous grain at `natural`.
+fn best_grain_start(input: &[Frame], natural: isize, nominal: isize) -> isize {
+    let mut best = nominal.max(0);
+    let mut best_score = f32::MIN;
+    for candidate in (nominal - SEARCH_FRAMES).max(0)..=nominal + SEARCH_FRAMES {
+        let score: f32 = (0..HOP_FRAMES as isize)
+            .map(|i| mono(frame_at(input, natural + i)) * mono(frame_at(input, candidate + i)))
+            .sum();
+        if score > best_score {
+            best_score = score;
+            best = candidate;
+        }
+    }
+    best
+}
+
+fn frame_at(input: &[Frame], index: isize) -> Frame {
+    if index < 0 {
+        return Frame::ZERO;
+    }
+    input.get(index as usize).copied().unwrap_or(Frame::ZERO)
+}
+
+fn mono(frame: Frame) -> f32 {
+    frame.left + frame.right
+}
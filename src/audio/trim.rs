@@ -0,0 +1,22 @@
+// The start point sits this far before the onset so the attack isn't cut.
+const ONSET_MARGIN_SECONDS: f64 = 0.005;
+
+/// Start and end points, as fractions of the file, around its non-silent part.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrimPoints {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl TrimPoints {
+    /// Points around frames `first` to `last` of a file `total` frames long, with the start
+    /// moved back a little from the onset.
+    pub fn around(first: u64, last: u64, total: u64, sample_rate: u32) -> Self {
+        let margin = (ONSET_MARGIN_SECONDS * sample_rate as f64) as u64;
+        let total = total.max(1) as f64;
+        Self {
+            start: first.saturating_sub(margin) as f64 / total,
+            end: ((last + 1) as f64 / total).min(1.0),
+        }
+    }
+}
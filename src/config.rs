@@ -0,0 +1,424 @@
+use crate::app::velocity::VelocityCurve;
+use crate::audio::capture::gain::InputClipMode;
+use clap::Parser;
+use push2::Push2Colors;
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CONFIG_DIR_NAME: &str = "pushboard";
+const CONFIG_FILE_NAME: &str = "config.toml";
+const DEFAULT_STORAGE_DIR_NAME: &str = "soundboard-recordings";
+const MAX_PRE_BUFFER_SECONDS: f64 = 30.0;
+const MAX_CAPTURE_GAIN: f32 = 16.0;
+const MAX_RETRO_SECONDS: f64 = 120.0;
+const MIN_THRESHOLD_DB: f64 = -90.0;
+const MAX_ARM_PRE_ROLL_SECONDS: f64 = 5.0;
+const MAX_ARM_SILENCE_SECONDS: f64 = 60.0;
+// Keeps a 32-bit float WAV of 48 kHz stereo well under the format's 4 GiB limit.
+const MAX_RECORDING_SECONDS: f64 = 3_600.0;
+const MAX_PALETTE_INDEX: u8 = 127;
+
+/// Command-line overrides. Anything given here wins over the config file.
+#[derive(Parser, Debug, Default)]
+#[command(version, about = "Ableton Push 2 sampler and soundboard")]
+pub struct Cli {
+    /// Path to the config file (defaults to $XDG_CONFIG_HOME/pushboard/config.toml)
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+    /// Directory where recordings are stored
+    #[arg(long)]
+    pub storage_dir: Option<PathBuf>,
+    /// PipeWire node name of pushboard's playback stream
+    #[arg(long)]
+    pub playback_node: Option<String>,
+    /// PipeWire sink used when routing to the mixer
+    #[arg(long)]
+    pub mixer_target: Option<String>,
+    /// PipeWire sink used when routing to the speakers
+    #[arg(long)]
+    pub default_target: Option<String>,
+    /// Seconds of audio kept before a recording starts
+    #[arg(long)]
+    pub pre_buffer_seconds: Option<f64>,
+    /// Linear gain applied to captured audio
+    #[arg(long)]
+    pub capture_gain: Option<f32>,
+    /// What happens to captured audio the gain pushes past full scale
+    #[arg(long, value_enum)]
+    pub capture_clip_mode: Option<InputClipMode>,
+    /// Longest recording, in seconds, before it stops on its own
+    #[arg(long)]
+    pub max_recording_seconds: Option<f64>,
+    /// Seconds of input kept for retroactive saves
+    #[arg(long)]
+    pub retro_seconds: Option<f64>,
+    /// Input level in dBFS that starts an armed recording
+    #[arg(long, allow_negative_numbers = true)]
+    pub arm_threshold_db: Option<f64>,
+    /// Seconds of silence that stop an armed recording (0 to never stop)
+    #[arg(long)]
+    pub arm_silence_seconds: Option<f64>,
+    /// Level in dBFS below which new recordings are trimmed as silence
+    #[arg(long, allow_negative_numbers = true)]
+    pub trim_threshold_db: Option<f64>,
+    /// Curve mapping pad velocity to gain
+    #[arg(long, value_enum)]
+    pub velocity_curve: Option<VelocityCurve>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub storage_dir: Option<PathBuf>,
+    pub routing: RoutingConfig,
+    pub capture: CaptureConfig,
+    pub colors: ColorConfig,
+    pub velocity: VelocityConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoutingConfig {
+    pub playback_node: String,
+    pub mixer_target: String,
+    pub default_target: String,
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        Self {
+            playback_node: "alsa_playback.pushboard".to_string(),
+            mixer_target: "MyMixer".to_string(),
+            default_target: "alsa_output.usb-Generic_USB_Audio-00.HiFi__Speaker__sink".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureConfig {
+    pub pre_buffer_seconds: f64,
+    pub gain: f32,
+    pub clip_mode: InputClipMode,
+    /// Recordings stop on their own after this long.
+    pub max_recording_seconds: f64,
+    /// Input kept for retroactive saves with Capture + pad; 0 turns them off.
+    pub retro_seconds: f64,
+    /// Level in dBFS that starts an armed recording and, below it, counts as silence.
+    pub arm_threshold_db: f64,
+    /// Audio kept from before the threshold was crossed.
+    pub arm_pre_roll_seconds: f64,
+    /// Silence that stops an armed recording; 0 keeps recording until the pad is pressed.
+    pub arm_silence_seconds: f64,
+    /// Moves a new recording's start and end points to where its audio begins and ends.
+    pub auto_trim: bool,
+    /// Level in dBFS below which auto-trim treats audio as silence.
+    pub trim_threshold_db: f64,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            pre_buffer_seconds: 1.0,
+            gain: 1.0,
+            clip_mode: InputClipMode::SoftClip,
+            max_recording_seconds: 600.0,
+            retro_seconds: 60.0,
+            arm_threshold_db: -40.0,
+            arm_pre_roll_seconds: 0.1,
+            arm_silence_seconds: 0.0,
+            auto_trim: true,
+            trim_threshold_db: -50.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct VelocityConfig {
+    /// Curve used by pads that don't choose their own.
+    pub curve: VelocityCurve,
+    /// Amplitudes (0-1) from the softest to the hardest hit, for the custom curve.
+    pub table: Vec<f64>,
+}
+
+impl Default for VelocityConfig {
+    fn default() -> Self {
+        Self {
+            curve: VelocityCurve::Linear,
+            table: vec![0.05, 0.3, 0.65, 1.0],
+        }
+    }
+}
+
+/// Push 2 palette indices used for pad and button LEDs.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorConfig {
+    pub off: u8,
+    pub has_file: u8,
+    pub recording: u8,
+    pub playing: u8,
+    pub selected: u8,
+    pub button_on: u8,
+}
+
+impl Default for ColorConfig {
+    fn default() -> Self {
+        Self {
+            off: Push2Colors::BLACK,
+            has_file: Push2Colors::BLUE_SKY,
+            recording: Push2Colors::RED,
+            playing: Push2Colors::PINK,
+            selected: Push2Colors::PURPLE,
+            button_on: Push2Colors::GREEN_PALE,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(&'static str, String),
+    NoAudioDir,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "cannot read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "invalid config {}: {}", path.display(), e),
+            ConfigError::Invalid(field, reason) => {
+                write!(f, "invalid value for `{}`: {}", field, reason)
+            }
+            ConfigError::NoAudioDir => write!(
+                f,
+                "could not find the XDG audio directory; set `storage_dir` in the config"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Read(_, e) => Some(e),
+            ConfigError::Parse(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file named on the command line, or the default one if it exists,
+    /// then applies the remaining command-line overrides and validates the result.
+    pub fn load(cli: &Cli) -> Result<Self, ConfigError> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None => match default_config_path() {
+                Some(path) if path.exists() => Self::from_file(&path)?,
+                _ => Self::default(),
+            },
+        };
+        config.apply_overrides(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_path_buf(), e))?;
+        toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))
+    }
+
+    fn apply_overrides(&mut self, cli: &Cli) {
+        if let Some(dir) = &cli.storage_dir {
+            self.storage_dir = Some(dir.clone());
+        }
+        if let Some(node) = &cli.playback_node {
+            self.routing.playback_node = node.clone();
+        }
+        if let Some(target) = &cli.mixer_target {
+            self.routing.mixer_target = target.clone();
+        }
+        if let Some(target) = &cli.default_target {
+            self.routing.default_target = target.clone();
+        }
+        if let Some(seconds) = cli.pre_buffer_seconds {
+            self.capture.pre_buffer_seconds = seconds;
+        }
+        if let Some(gain) = cli.capture_gain {
+            self.capture.gain = gain;
+        }
+        if let Some(mode) = cli.capture_clip_mode {
+            self.capture.clip_mode = mode;
+        }
+        if let Some(seconds) = cli.max_recording_seconds {
+            self.capture.max_recording_seconds = seconds;
+        }
+        if let Some(seconds) = cli.retro_seconds {
+            self.capture.retro_seconds = seconds;
+        }
+        if let Some(db) = cli.arm_threshold_db {
+            self.capture.arm_threshold_db = db;
+        }
+        if let Some(seconds) = cli.arm_silence_seconds {
+            self.capture.arm_silence_seconds = seconds;
+        }
+        if let Some(db) = cli.trim_threshold_db {
+            self.capture.trim_threshold_db = db;
+        }
+        if let Some(curve) = cli.velocity_curve {
+            self.velocity.curve = curve;
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (field, value) in [
+            ("routing.playback_node", &self.routing.playback_node),
+            ("routing.mixer_target", &self.routing.mixer_target),
+            ("routing.default_target", &self.routing.default_target),
+        ] {
+            if value.trim().is_empty() {
+                return Err(ConfigError::Invalid(
+                    field,
+                    "node name must not be empty".into(),
+                ));
+            }
+        }
+
+        let pre_buffer = self.capture.pre_buffer_seconds;
+        if !(0.0..=MAX_PRE_BUFFER_SECONDS).contains(&pre_buffer) {
+            return Err(ConfigError::Invalid(
+                "capture.pre_buffer_seconds",
+                format!(
+                    "{} is outside 0 to {} seconds",
+                    pre_buffer, MAX_PRE_BUFFER_SECONDS
+                ),
+            ));
+        }
+
+        let gain = self.capture.gain;
+        if !gain.is_finite() || gain <= 0.0 || gain > MAX_CAPTURE_GAIN {
+            return Err(ConfigError::Invalid(
+                "capture.gain",
+                format!("{} must be above 0 and at most {}", gain, MAX_CAPTURE_GAIN),
+            ));
+        }
+
+        let retro = self.capture.retro_seconds;
+        if !(0.0..=MAX_RETRO_SECONDS).contains(&retro) {
+            return Err(ConfigError::Invalid(
+                "capture.retro_seconds",
+                format!("{} is outside 0 to {} seconds", retro, MAX_RETRO_SECONDS),
+            ));
+        }
+
+        for (field, value, min, max, unit) in [
+            (
+                "capture.arm_threshold_db",
+                self.capture.arm_threshold_db,
+                MIN_THRESHOLD_DB,
+                0.0,
+                "dBFS",
+            ),
+            (
+                "capture.trim_threshold_db",
+                self.capture.trim_threshold_db,
+                MIN_THRESHOLD_DB,
+                0.0,
+                "dBFS",
+            ),
+            (
+                "capture.arm_pre_roll_seconds",
+                self.capture.arm_pre_roll_seconds,
+                0.0,
+                MAX_ARM_PRE_ROLL_SECONDS,
+                "seconds",
+            ),
+            (
+                "capture.arm_silence_seconds",
+                self.capture.arm_silence_seconds,
+                0.0,
+                MAX_ARM_SILENCE_SECONDS,
+                "seconds",
+            ),
+        ] {
+            if !(min..=max).contains(&value) {
+                return Err(ConfigError::Invalid(
+                    field,
+                    format!("{} is outside {} to {} {}", value, min, max, unit),
+                ));
+            }
+        }
+
+        let max_length = self.capture.max_recording_seconds;
+        if !(max_length > 0.0 && max_length <= MAX_RECORDING_SECONDS) {
+            return Err(ConfigError::Invalid(
+                "capture.max_recording_seconds",
+                format!(
+                    "{} must be above 0 and at most {} seconds",
+                    max_length, MAX_RECORDING_SECONDS
+                ),
+            ));
+        }
+
+        let colors = &self.colors;
+        for (field, value) in [
+            ("colors.off", colors.off),
+            ("colors.has_file", colors.has_file),
+            ("colors.recording", colors.recording),
+            ("colors.playing", colors.playing),
+            ("colors.selected", colors.selected),
+            ("colors.button_on", colors.button_on),
+        ] {
+            if value > MAX_PALETTE_INDEX {
+                return Err(ConfigError::Invalid(
+                    field,
+                    format!("{} is not a palette index (0-{})", value, MAX_PALETTE_INDEX),
+                ));
+            }
+        }
+
+        let table = &self.velocity.table;
+        if table.len() < 2 {
+            return Err(ConfigError::Invalid(
+                "velocity.table",
+                "needs at least two points".into(),
+            ));
+        }
+        if let Some(value) = table.iter().find(|v| !(0.0..=1.0).contains(*v)) {
+            return Err(ConfigError::Invalid(
+                "velocity.table",
+                format!("{} is outside 0 to 1", value),
+            ));
+        }
+
+        if let Some(dir) = &self.storage_dir {
+            if dir.as_os_str().is_empty() {
+                return Err(ConfigError::Invalid(
+                    "storage_dir",
+                    "path must not be empty".into(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The configured storage directory, or `soundboard-recordings` in the XDG audio dir.
+    pub fn storage_dir(&self) -> Result<PathBuf, ConfigError> {
+        match &self.storage_dir {
+            Some(dir) => Ok(dir.clone()),
+            None => dirs::audio_dir()
+                .map(|dir| dir.join(DEFAULT_STORAGE_DIR_NAME))
+                .ok_or(ConfigError::NoAudioDir),
+        }
+    }
+}
+
+pub fn default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(CONFIG_DIR_NAME).join(CONFIG_FILE_NAME))
+}
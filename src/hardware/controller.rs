@@ -0,0 +1,92 @@
+use embedded_graphics::{pixelcolor::Bgr565, prelude::*};
+use push2::{ControlName, GuiApi, PadCoord, Push2, Push2Event};
+use std::error::Error;
+
+/// Everything pushboard needs from a Push 2: LEDs, the display and the event stream.
+///
+/// `Push2` implements this for the real device; `VirtualPush2` implements it in memory.
+pub trait Controller {
+    type Display: DrawTarget<Color = Bgr565, Error: Error + 'static>;
+
+    fn poll_event(&mut self) -> Option<Push2Event>;
+    fn set_pad_color(&mut self, coord: PadCoord, color: u8) -> Result<(), Box<dyn Error>>;
+    fn set_button_light(&mut self, name: ControlName, color: u8) -> Result<(), Box<dyn Error>>;
+
+    /// MIDI note address of the pad at `coord`.
+    fn pad_address(&self, coord: PadCoord) -> Option<u8>;
+    fn pad_coord(&self, address: u8) -> Option<PadCoord>;
+
+    fn display(&mut self) -> &mut Self::Display;
+    fn draw_waveform_peaks(
+        &mut self,
+        peaks: &[(f32, f32)],
+        color: Bgr565,
+    ) -> Result<(), Box<dyn Error>>;
+    fn draw_encoder_outline(&mut self, index: u8, color: Bgr565) -> Result<(), Box<dyn Error>>;
+    fn draw_encoder_bar(
+        &mut self,
+        index: u8,
+        value: i32,
+        color: Bgr565,
+    ) -> Result<(), Box<dyn Error>>;
+    fn flush_display(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+impl Controller for Push2 {
+    type Display = push2::Push2Display;
+
+    fn poll_event(&mut self) -> Option<Push2Event> {
+        Push2::poll_event(self)
+    }
+
+    fn set_pad_color(&mut self, coord: PadCoord, color: u8) -> Result<(), Box<dyn Error>> {
+        Push2::set_pad_color(self, coord, color)?;
+        Ok(())
+    }
+
+    fn set_button_light(&mut self, name: ControlName, color: u8) -> Result<(), Box<dyn Error>> {
+        Push2::set_button_light(self, name, color)?;
+        Ok(())
+    }
+
+    fn pad_address(&self, coord: PadCoord) -> Option<u8> {
+        self.button_map.get_note_address(coord)
+    }
+
+    fn pad_coord(&self, address: u8) -> Option<PadCoord> {
+        self.button_map.get_note(address)
+    }
+
+    fn display(&mut self) -> &mut Self::Display {
+        &mut self.display
+    }
+
+    fn draw_waveform_peaks(
+        &mut self,
+        peaks: &[(f32, f32)],
+        color: Bgr565,
+    ) -> Result<(), Box<dyn Error>> {
+        self.display.draw_waveform_peaks(peaks, color)?;
+        Ok(())
+    }
+
+    fn draw_encoder_outline(&mut self, index: u8, color: Bgr565) -> Result<(), Box<dyn Error>> {
+        self.display.draw_encoder_outline(index, color)?;
+        Ok(())
+    }
+
+    fn draw_encoder_bar(
+        &mut self,
+        index: u8,
+        value: i32,
+        color: Bgr565,
+    ) -> Result<(), Box<dyn Error>> {
+        self.display.draw_encoder_bar(index, value, color)?;
+        Ok(())
+    }
+
+    fn flush_display(&mut self) -> Result<(), Box<dyn Error>> {
+        self.display.flush()?;
+        Ok(())
+    }
+}
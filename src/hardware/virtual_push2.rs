@@ -0,0 +1,278 @@
+use crate::hardware::controller::Controller;
+use embedded_graphics::{
+    pixelcolor::Bgr565,
+    prelude::*,
+    primitives::{Line, PrimitiveStyle},
+};
+use push2::{ControlName, EncoderName, PadCoord, Push2Event};
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::error::Error;
+
+pub const DISPLAY_WIDTH: u32 = 960;
+pub const DISPLAY_HEIGHT: u32 = 160;
+pub const ENCODER_COUNT: usize = 8;
+const PAD_GRID_SIZE: u8 = 8;
+// The bottom-left pad sends note 36 and notes rise left to right, bottom to top.
+const PAD_NOTE_BASE: u8 = 36;
+
+/// In-memory 960x160 framebuffer standing in for the Push 2 display.
+pub struct VirtualDisplay {
+    pixels: Vec<Bgr565>,
+}
+
+impl VirtualDisplay {
+    fn new() -> Self {
+        Self {
+            pixels: vec![Bgr565::BLACK; (DISPLAY_WIDTH * DISPLAY_HEIGHT) as usize],
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Option<Bgr565> {
+        if x >= DISPLAY_WIDTH || y >= DISPLAY_HEIGHT {
+            return None;
+        }
+        Some(self.pixels[(y * DISPLAY_WIDTH + x) as usize])
+    }
+
+    pub fn count_pixels(&self, color: Bgr565) -> usize {
+        self.pixels.iter().filter(|pixel| **pixel == color).count()
+    }
+
+    /// True if any pixel in column `x` has `color`.
+    pub fn column_contains(&self, x: u32, color: Bgr565) -> bool {
+        (0..DISPLAY_HEIGHT).any(|y| self.pixel(x, y) == Some(color))
+    }
+}
+
+impl OriginDimensions for VirtualDisplay {
+    fn size(&self) -> Size {
+        Size::new(DISPLAY_WIDTH, DISPLAY_HEIGHT)
+    }
+}
+
+impl DrawTarget for VirtualDisplay {
+    type Color = Bgr565;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x >= 0
+                && point.y >= 0
+                && (point.x as u32) < DISPLAY_WIDTH
+                && (point.y as u32) < DISPLAY_HEIGHT
+            {
+                self.pixels[(point.y as u32 * DISPLAY_WIDTH + point.x as u32) as usize] = color;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What the last frame drew for one encoder slot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EncoderDisplay {
+    pub outline: Option<Bgr565>,
+    pub bar: Option<(i32, Bgr565)>,
+}
+
+/// A scriptable Push 2 with no hardware behind it.
+///
+/// Queue input with the `press_*`/`twist_encoder` helpers, run the app's event handling,
+/// then assert on the LED and display state it left behind.
+pub struct VirtualPush2 {
+    events: VecDeque<Push2Event>,
+    pad_colors: [[u8; PAD_GRID_SIZE as usize]; PAD_GRID_SIZE as usize],
+    button_lights: HashMap<ControlName, u8>,
+    display: VirtualDisplay,
+    encoders: [EncoderDisplay; ENCODER_COUNT],
+    last_frame_encoders: [EncoderDisplay; ENCODER_COUNT],
+    flush_count: usize,
+}
+
+impl Default for VirtualPush2 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualPush2 {
+    pub fn new() -> Self {
+        let blank = EncoderDisplay {
+            outline: None,
+            bar: None,
+        };
+        Self {
+            events: VecDeque::new(),
+            pad_colors: [[0; PAD_GRID_SIZE as usize]; PAD_GRID_SIZE as usize],
+            button_lights: HashMap::new(),
+            display: VirtualDisplay::new(),
+            encoders: [blank; ENCODER_COUNT],
+            last_frame_encoders: [blank; ENCODER_COUNT],
+            flush_count: 0,
+        }
+    }
+
+    pub fn push_event(&mut self, event: Push2Event) {
+        self.events.push_back(event);
+    }
+
+    pub fn press_pad(&mut self, x: u8, y: u8, velocity: u8) {
+        self.push_event(Push2Event::PadPressed {
+            coord: PadCoord { x, y },
+            velocity,
+        });
+    }
+
+    pub fn release_pad(&mut self, x: u8, y: u8) {
+        self.push_event(Push2Event::PadReleased {
+            coord: PadCoord { x, y },
+        });
+    }
+
+    pub fn press_button(&mut self, name: ControlName) {
+        self.push_event(Push2Event::ButtonPressed {
+            name,
+            velocity: 127,
+        });
+    }
+
+    pub fn release_button(&mut self, name: ControlName) {
+        self.push_event(Push2Event::ButtonReleased { name });
+    }
+
+    /// Queues a twist of `delta` detents, encoded the way the device sends it.
+    pub fn twist_encoder(&mut self, name: EncoderName, delta: i32) {
+        let raw_delta = if delta < 0 {
+            (128 + delta.max(-63)) as u8
+        } else {
+            delta.min(63) as u8
+        };
+        self.push_event(Push2Event::EncoderTwisted {
+            name,
+            value: delta,
+            raw_delta,
+        });
+    }
+
+    pub fn pending_events(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn pad_color(&self, x: u8, y: u8) -> u8 {
+        self.pad_colors[y as usize][x as usize]
+    }
+
+    pub fn button_light(&self, name: ControlName) -> u8 {
+        self.button_lights.get(&name).copied().unwrap_or(0)
+    }
+
+    pub fn screen(&self) -> &VirtualDisplay {
+        &self.display
+    }
+
+    /// Encoder state as of the most recent `flush_display`.
+    pub fn encoder(&self, index: usize) -> EncoderDisplay {
+        self.last_frame_encoders[index]
+    }
+
+    pub fn flush_count(&self) -> usize {
+        self.flush_count
+    }
+}
+
+impl Controller for VirtualPush2 {
+    type Display = VirtualDisplay;
+
+    fn poll_event(&mut self) -> Option<Push2Event> {
+        self.events.pop_front()
+    }
+
+    fn set_pad_color(&mut self, coord: PadCoord, color: u8) -> Result<(), Box<dyn Error>> {
+        if coord.x >= PAD_GRID_SIZE || coord.y >= PAD_GRID_SIZE {
+            return Err(format!("pad ({}, {}) is off the grid", coord.x, coord.y).into());
+        }
+        self.pad_colors[coord.y as usize][coord.x as usize] = color;
+        Ok(())
+    }
+
+    fn set_button_light(&mut self, name: ControlName, color: u8) -> Result<(), Box<dyn Error>> {
+        self.button_lights.insert(name, color);
+        Ok(())
+    }
+
+    fn pad_address(&self, coord: PadCoord) -> Option<u8> {
+        if coord.x >= PAD_GRID_SIZE || coord.y >= PAD_GRID_SIZE {
+            return None;
+        }
+        Some(PAD_NOTE_BASE + (PAD_GRID_SIZE - 1 - coord.y) * PAD_GRID_SIZE + coord.x)
+    }
+
+    fn pad_coord(&self, address: u8) -> Option<PadCoord> {
+        let index = address.checked_sub(PAD_NOTE_BASE)?;
+        if index >= PAD_GRID_SIZE * PAD_GRID_SIZE {
+            return None;
+        }
+        Some(PadCoord {
+            x: index % PAD_GRID_SIZE,
+            y: PAD_GRID_SIZE - 1 - index / PAD_GRID_SIZE,
+        })
+    }
+
+    fn display(&mut self) -> &mut Self::Display {
+        &mut self.display
+    }
+
+    fn draw_waveform_peaks(
+        &mut self,
+        peaks: &[(f32, f32)],
+        color: Bgr565,
+    ) -> Result<(), Box<dyn Error>> {
+        let mid = DISPLAY_HEIGHT as f32 / 2.0;
+        let style = PrimitiveStyle::with_stroke(color, 1);
+        for (x, (min, max)) in peaks.iter().take(DISPLAY_WIDTH as usize).enumerate() {
+            let top = (mid - max.clamp(-1.0, 1.0) * mid).round() as i32;
+            let bottom = (mid - min.clamp(-1.0, 1.0) * mid).round() as i32;
+            Line::new(Point::new(x as i32, top), Point::new(x as i32, bottom))
+                .into_styled(style)
+                .draw(&mut self.display)?;
+        }
+        Ok(())
+    }
+
+    fn draw_encoder_outline(&mut self, index: u8, color: Bgr565) -> Result<(), Box<dyn Error>> {
+        let slot = self
+            .encoders
+            .get_mut(index as usize)
+            .ok_or("encoder index out of range")?;
+        slot.outline = Some(color);
+        Ok(())
+    }
+
+    fn draw_encoder_bar(
+        &mut self,
+        index: u8,
+        value: i32,
+        color: Bgr565,
+    ) -> Result<(), Box<dyn Error>> {
+        let slot = self
+            .encoders
+            .get_mut(index as usize)
+            .ok_or("encoder index out of range")?;
+        slot.bar = Some((value, color));
+        Ok(())
+    }
+
+    fn flush_display(&mut self) -> Result<(), Box<dyn Error>> {
+        self.last_frame_encoders = self.encoders;
+        self.encoders = [EncoderDisplay {
+            outline: None,
+            bar: None,
+        }; ENCODER_COUNT];
+        self.flush_count += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,4 @@
+pub mod app;
+pub mod audio;
+pub mod config;
+pub mod hardware;
@@ -1,10 +1,17 @@
-mod app;
-pub mod audio;
-
+use clap::Parser;
+use pushboard::app;
+use pushboard::config::{Cli, Config};
 use std::error::Error;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     env_logger::init();
-    app::run().await
+    let config = match Config::load(&Cli::parse()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("pushboard: {}", e);
+            std::process::exit(2);
+        }
+    };
+    app::run(config).await
 }
@@ -0,0 +1,1050 @@
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use kira::Frame;
+use kira::sound::static_sound::{StaticSoundData, StaticSoundSettings};
+use pushboard::app::state::AudioCommand;
+use pushboard::audio::capture::file_backend::WavFileCapture;
+use pushboard::audio::capture::gain::InputClipMode;
+use pushboard::audio::capture::monitor::InputMonitor;
+use pushboard::audio::capture::{
+    CaptureBackend, CaptureEngine, CaptureFormat, RING_CAPACITY, capture_channel,
+};
+use pushboard::audio::effects::{FilterKind, PadEffects};
+use pushboard::audio::envelope::Envelope;
+use pushboard::audio::player::{KiraCommand, KiraPlayRequest, Player};
+use pushboard::audio::routing::{Graph, LinkPlan};
+use pushboard::audio::stretch::{self, StretchParams};
+use pushboard::config::CaptureConfig;
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const RATE: u32 = 48_000;
+
+/// Writes a stereo float WAV whose samples are `signal(seconds)`.
+fn write_fixture(path: &Path, seconds: f64, signal: impl Fn(f64) -> f32) {
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: RATE,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut writer = WavWriter::create(path, spec).unwrap();
+    for n in 0..(seconds * RATE as f64) as usize {
+        let sample = signal(n as f64 / RATE as f64);
+        writer.write_sample(sample).unwrap();
+        writer.write_sample(sample).unwrap();
+    }
+    writer.finalize().unwrap();
+}
+
+fn tone(t: f64) -> f32 {
+    (t * 440.0 * std::f64::consts::TAU).sin() as f32 * 0.5
+}
+
+fn read_samples(path: &Path) -> Vec<f32> {
+    WavReader::open(path)
+        .unwrap()
+        .samples::<f32>()
+        .map(Result::unwrap)
+        .collect()
+}
+
+fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0, |peak, s| peak.max(s.abs()))
+}
+
+fn play(
+    player: &mut Player<kira::backend::mock::MockBackend>,
+    path: &Path,
+    settings: StaticSoundSettings,
+) {
+    play_pad(player, 1, None, path, settings);
+}
+
+fn play_pad(
+    player: &mut Player<kira::backend::mock::MockBackend>,
+    pad_key: u8,
+    choke_group: Option<u8>,
+    path: &Path,
+    settings: StaticSoundSettings,
+) {
+    play_voices(player, pad_key, choke_group, 1, path, settings);
+}
+
+fn play_voices(
+    player: &mut Player<kira::backend::mock::MockBackend>,
+    pad_key: u8,
+    choke_group: Option<u8>,
+    voices: u8,
+    path: &Path,
+    settings: StaticSoundSettings,
+) {
+    player.handle_command(KiraCommand::Play(KiraPlayRequest {
+        pad_key,
+        sound_data: StaticSoundData::from_file(path).unwrap(),
+        settings,
+        choke_group,
+        voices,
+        effects: PadEffects::default(),
+        generation: 0,
+    }));
+}
+
+#[test]
+fn recording_keeps_pre_roll_and_everything_after_start() {
+    let dir = TempDir::new().unwrap();
+    let fixture_path = dir.path().join("fixture.wav");
+    let out_path = dir.path().join("pad_0_0.wav");
+    write_fixture(&fixture_path, 1.0, tone);
+
+    let fixture = WavFileCapture::open(&fixture_path).unwrap();
+    let mut engine = CaptureEngine::new(
+        CaptureConfig {
+            pre_buffer_seconds: 0.25,
+            gain: 1.0,
+            clip_mode: InputClipMode::Clip,
+            ..CaptureConfig::default()
+        },
+        Arc::default(),
+    );
+    engine.set_format(fixture.format());
+
+    let samples = fixture.samples();
+    let half = samples.len() / 2;
+    engine.process(&samples[..half]);
+    assert!(
+        engine
+            .handle_command(AudioCommand::Start(out_path.clone()))
+            .is_none()
+    );
+    engine.process(&samples[half..]);
+    let saved = engine.handle_command(AudioCommand::Stop).unwrap().finish();
+
+    assert_eq!(saved, out_path);
+    let recorded = read_samples(&out_path);
+    let expected_frames = (0.25 * RATE as f64) as usize + RATE as usize / 2;
+    assert_eq!(recorded.len(), expected_frames * 2);
+    // The recording ends exactly where the fixture does.
+    assert_eq!(recorded.last(), samples.last());
+}
+
+#[test]
+fn recordings_stream_to_a_playable_part_file_and_stop_at_the_limit() {
+    let dir = TempDir::new().unwrap();
+    let out_path = dir.path().join("pad_2_0.wav");
+    let part_path = dir.path().join("pad_2_0.wav.part");
+    let mut engine = CaptureEngine::new(
+        CaptureConfig {
+            pre_buffer_seconds: 0.0,
+            max_recording_seconds: 2.0,
+            ..CaptureConfig::default()
+        },
+        Arc::default(),
+    );
+    engine.set_format(CaptureFormat {
+        rate: RATE,
+        channels: 2,
+    });
+    engine.handle_command(AudioCommand::Start(out_path.clone()));
+
+    // Mid-recording the audio is already on disk, with a header that says how much.
+    assert!(engine.process(&vec![0.25; RATE as usize * 3]).is_none());
+    assert!(!out_path.exists());
+    assert_eq!(
+        WavReader::open(&part_path).unwrap().duration(),
+        RATE * 3 / 2
+    );
+
+    // The next block crosses the limit: the recording stops at exactly two seconds.
+    let pending = engine.process(&vec![0.25; RATE as usize * 2]).unwrap();
+    assert!(!engine.is_recording());
+    assert_eq!(pending.finish(), out_path);
+    assert!(!part_path.exists());
+    assert_eq!(read_samples(&out_path).len(), RATE as usize * 2 * 2);
+}
+
+#[test]
+fn history_saves_the_last_seconds_and_supplies_the_pre_roll() {
+    let dir = TempDir::new().unwrap();
+    let retro_path = dir.path().join("pad_3_0.wav");
+    let recording_path = dir.path().join("pad_3_1.wav");
+    let mut engine = CaptureEngine::new(
+        CaptureConfig {
+            pre_buffer_seconds: 0.25,
+            retro_seconds: 1.0,
+            ..CaptureConfig::default()
+        },
+        Arc::default(),
+    );
+    engine.set_format(CaptureFormat {
+        rate: RATE,
+        channels: 2,
+    });
+    engine.process(&vec![0.1; RATE as usize * 2 * 2]);
+    engine.process(&vec![0.5; RATE as usize * 2]);
+
+    // Only the last second is kept, and saving it doesn't need a recording.
+    let saved = engine
+        .handle_command(AudioCommand::SaveHistory(retro_path.clone()))
+        .unwrap()
+        .finish();
+    assert_eq!(saved, retro_path);
+    let retro = read_samples(&retro_path);
+    assert_eq!(retro.len(), RATE as usize * 2);
+    assert!(retro.iter().all(|&s| s == 0.5));
+    assert!(!engine.is_recording());
+
+    // A normal recording still only gets the pre-buffer as pre-roll.
+    engine.handle_command(AudioCommand::Start(recording_path.clone()));
+    engine.handle_command(AudioCommand::Stop).unwrap().finish();
+    assert_eq!(
+        read_samples(&recording_path).len(),
+        (0.25 * RATE as f64) as usize * 2
+    );
+}
+
+#[test]
+fn armed_recording_starts_at_the_threshold_and_stops_on_silence() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("pad_4_0.wav");
+    let monitor = Arc::new(InputMonitor::default());
+    let mut engine = CaptureEngine::new(
+        CaptureConfig {
+            pre_buffer_seconds: 0.0,
+            retro_seconds: 0.0,
+            arm_threshold_db: -20.0,
+            arm_pre_roll_seconds: 0.01,
+            arm_silence_seconds: 0.5,
+            ..CaptureConfig::default()
+        },
+        monitor.clone(),
+    );
+    engine.set_format(CaptureFormat {
+        rate: RATE,
+        channels: 1,
+    });
+    let pre_roll = RATE as usize / 100;
+
+    engine.handle_command(AudioCommand::Arm(path.clone()));
+    assert!(monitor.is_armed());
+    assert!(engine.process(&vec![0.01; RATE as usize / 2]).is_none());
+    assert!(!engine.is_recording());
+
+    // The threshold is crossed 200 samples into the block, so the pre-roll reaches back
+    // into the previous one.
+    let mut block = vec![0.01; 200];
+    block.extend(vec![0.5; RATE as usize / 2]);
+    assert!(engine.process(&block).is_none());
+    assert!(engine.is_recording());
+    assert!(!monitor.is_armed());
+
+    // Half a second of silence ends it, keeping a tail as long as the pre-roll.
+    let pending = engine.process(&vec![0.01; RATE as usize]).unwrap();
+    assert!(!engine.is_recording());
+    pending.finish();
+    let recorded = read_samples(&path);
+    assert_eq!(recorded.len(), pre_roll + RATE as usize / 2 + pre_roll);
+    assert!(recorded[..pre_roll].iter().all(|&s| s == 0.01));
+    assert_eq!(recorded[pre_roll], 0.5);
+    assert_eq!(recorded[pre_roll + RATE as usize / 2 - 1], 0.5);
+    assert!(
+        recorded[recorded.len() - pre_roll..]
+            .iter()
+            .all(|&s| s == 0.01)
+    );
+
+    // Stopping by hand during a quiet stretch keeps the same tail.
+    let stopped_path = dir.path().join("pad_4_2.wav");
+    engine.handle_command(AudioCommand::Arm(stopped_path.clone()));
+    assert!(engine.process(&vec![0.5; RATE as usize / 4]).is_none());
+    assert!(engine.process(&vec![0.01; RATE as usize / 4]).is_none());
+    engine.handle_command(AudioCommand::Stop).unwrap().finish();
+    let recorded = read_samples(&stopped_path);
+    assert_eq!(recorded.len(), pre_roll + RATE as usize / 4 + pre_roll);
+    assert!(
+        recorded[recorded.len() - pre_roll..]
+            .iter()
+            .all(|&s| s == 0.01)
+    );
+
+    // Disarming before anything crosses the threshold records nothing.
+    engine.handle_command(AudioCommand::Arm(dir.path().join("pad_4_1.wav")));
+    assert!(engine.handle_command(AudioCommand::Stop).is_none());
+    assert!(!monitor.is_armed());
+}
+
+#[test]
+fn a_recording_that_cannot_start_is_reported() {
+    let dir = TempDir::new().unwrap();
+    // A file where the bank directory should be leaves nowhere to record to.
+    let blocker = dir.path().join("bank_01");
+    std::fs::write(&blocker, b"").unwrap();
+    let path = blocker.join("pad_0_0.wav");
+    let monitor = Arc::new(InputMonitor::default());
+    let mut engine = CaptureEngine::new(
+        CaptureConfig {
+            arm_threshold_db: -20.0,
+            ..CaptureConfig::default()
+        },
+        monitor.clone(),
+    );
+    engine.set_format(CaptureFormat {
+        rate: RATE,
+        channels: 1,
+    });
+
+    engine.handle_command(AudioCommand::Arm(path.clone()));
+    assert!(engine.process(&[0.5; 480]).is_none());
+    assert!(!engine.is_recording());
+    assert!(!monitor.is_armed());
+    assert_eq!(engine.take_failed(), Some(path));
+    assert_eq!(engine.take_failed(), None);
+}
+
+#[test]
+fn saving_an_empty_history_is_reported_as_failed() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("pad_0_0.wav");
+    let mut engine =
+        CaptureEngine::new(CaptureConfig::default(), Arc::new(InputMonitor::default()));
+
+    // No format yet: the input hasn't started.
+    assert!(
+        engine
+            .handle_command(AudioCommand::SaveHistory(path.clone()))
+            .is_none()
+    );
+    assert_eq!(engine.take_failed(), Some(path.clone()));
+
+    // Started, but nothing captured.
+    engine.set_format(CaptureFormat {
+        rate: RATE,
+        channels: 1,
+    });
+    assert!(
+        engine
+            .handle_command(AudioCommand::SaveHistory(path.clone()))
+            .is_none()
+    );
+    assert_eq!(engine.take_failed(), Some(path.clone()));
+    assert!(!path.exists());
+}
+
+#[test]
+fn file_backend_feeds_the_engine_with_gain_and_clamping() {
+    let dir = TempDir::new().unwrap();
+    let fixture_path = dir.path().join("fixture.wav");
+    let out_path = dir.path().join("pad_1_0.wav");
+    write_fixture(&fixture_path, 0.2, |t| if t < 0.1 { 0.3 } else { 0.8 });
+
+    let mut engine = CaptureEngine::new(
+        CaptureConfig {
+            pre_buffer_seconds: 0.0,
+            gain: 2.0,
+            clip_mode: InputClipMode::Clip,
+            ..CaptureConfig::default()
+        },
+        Arc::default(),
+    );
+    let (input, mut reader) = capture_channel(Arc::default());
+    let backend = WavFileCapture::open(&fixture_path)
+        .unwrap()
+        .block_frames(256);
+    engine.set_format(backend.format());
+    engine.handle_command(AudioCommand::Start(out_path.clone()));
+
+    backend.run(input).unwrap();
+    reader.drain_into(&mut engine);
+    engine.handle_command(AudioCommand::Stop).unwrap().finish();
+
+    let recorded = read_samples(&out_path);
+    assert_eq!(recorded.len(), (0.2 * RATE as f64) as usize * 2);
+    assert!((recorded[0] - 0.6).abs() < 1e-6);
+    assert_eq!(*recorded.last().unwrap(), 1.0);
+}
+
+/// Records `input` through a mono engine with the given gain and clip mode.
+fn record_with(dir: &TempDir, gain: f32, clip_mode: InputClipMode, input: &[f32]) -> Vec<f32> {
+    let path = dir.path().join("clip_mode.wav");
+    let mut engine = CaptureEngine::new(
+        CaptureConfig {
+            pre_buffer_seconds: 0.0,
+            gain,
+            clip_mode,
+            ..CaptureConfig::default()
+        },
+        Arc::default(),
+    );
+    engine.set_format(CaptureFormat {
+        rate: RATE,
+        channels: 1,
+    });
+    engine.handle_command(AudioCommand::Start(path.clone()));
+    engine.process(input);
+    engine.handle_command(AudioCommand::Stop).unwrap().finish();
+    read_samples(&path)
+}
+
+#[test]
+fn clip_modes_shape_what_the_gain_pushes_past_full_scale() {
+    let dir = TempDir::new().unwrap();
+    // 0.1 s at 0.3, then 0.1 s at 0.9; doubled, the second half is past full scale.
+    let input: Vec<f32> = (0..9_600)
+        .map(|n| if n < 4_800 { 0.3 } else { 0.9 })
+        .collect();
+
+    let soft = record_with(&dir, 2.0, InputClipMode::SoftClip, &input);
+    assert!((soft[0] - 0.6).abs() < 1e-6);
+    let loud = *soft.last().unwrap();
+    assert!(loud > 0.75 && loud < 1.0);
+
+    let limited = record_with(&dir, 2.0, InputClipMode::Limiter, &input);
+    assert!((limited[0] - 0.6).abs() < 1e-6);
+    assert!(limited.iter().all(|s| s.abs() <= 0.966 + 1e-6));
+    assert!(*limited.last().unwrap() > 0.9);
+
+    let raw = record_with(&dir, 2.0, InputClipMode::Raw, &input);
+    assert!((raw.last().unwrap() - 1.8).abs() < 1e-6);
+
+    // The gain and mode can change while listening.
+    let path = dir.path().join("changed.wav");
+    let mut engine = CaptureEngine::new(CaptureConfig::default(), Arc::default());
+    engine.set_format(CaptureFormat {
+        rate: RATE,
+        channels: 1,
+    });
+    engine.handle_command(AudioCommand::SetInputGain(0.5));
+    engine.handle_command(AudioCommand::SetClipMode(InputClipMode::Raw));
+    engine.handle_command(AudioCommand::Start(path.clone()));
+    engine.process(&[0.8; 480]);
+    engine.handle_command(AudioCommand::Stop).unwrap().finish();
+    assert!((read_samples(&path)[0] - 0.4).abs() < 1e-6);
+}
+
+#[test]
+fn a_full_ring_drops_whole_blocks_and_counts_overflows() {
+    let dir = TempDir::new().unwrap();
+    let out_path = dir.path().join("overflow.wav");
+    let monitor = Arc::new(InputMonitor::default());
+    let (mut input, mut reader) = capture_channel(monitor.clone());
+    let mut engine = CaptureEngine::new(
+        CaptureConfig {
+            pre_buffer_seconds: 0.0,
+            gain: 1.0,
+            clip_mode: InputClipMode::Raw,
+            ..CaptureConfig::default()
+        },
+        Arc::default(),
+    );
+    input.set_format(CaptureFormat {
+        rate: RATE,
+        channels: 2,
+    });
+    reader.drain_into(&mut engine);
+    assert_eq!(
+        engine.format(),
+        Some(CaptureFormat {
+            rate: RATE,
+            channels: 2
+        })
+    );
+    engine.handle_command(AudioCommand::Start(out_path.clone()));
+
+    // The recording thread stalls: the ring fills, then a block that doesn't fit is lost.
+    input.push_samples(&vec![0.5; RING_CAPACITY - 2]);
+    input.push_samples(&[0.9; 4]);
+    assert_eq!(monitor.overflows(), 1);
+
+    // Once drained there is room again, and nothing queued was lost.
+    reader.drain_into(&mut engine);
+    input.push_samples(&[0.25; 4]);
+    reader.drain_into(&mut engine);
+    assert_eq!(monitor.overflows(), 1);
+    engine.handle_command(AudioCommand::Stop).unwrap().finish();
+
+    let recorded = read_samples(&out_path);
+    assert_eq!(recorded.len(), RING_CAPACITY + 2);
+    assert!(recorded.iter().all(|&s| s == 0.5 || s == 0.25));
+    assert_eq!(recorded[RING_CAPACITY - 2..], [0.25; 4]);
+}
+
+#[test]
+fn stop_without_start_writes_nothing() {
+    let mut engine = CaptureEngine::new(CaptureConfig::default(), Arc::default());
+    engine.set_format(pushboard::audio::capture::CaptureFormat {
+        rate: RATE,
+        channels: 2,
+    });
+    engine.process(&[0.1; 512]);
+    assert!(engine.handle_command(AudioCommand::Stop).is_none());
+}
+
+#[test]
+fn saves_report_where_their_audio_begins_and_ends() {
+    let dir = TempDir::new().unwrap();
+    let mut engine = CaptureEngine::new(
+        CaptureConfig {
+            pre_buffer_seconds: 0.0,
+            retro_seconds: 1.0,
+            trim_threshold_db: -50.0,
+            ..CaptureConfig::default()
+        },
+        Arc::default(),
+    );
+    engine.set_format(CaptureFormat {
+        rate: RATE,
+        channels: 2,
+    });
+    // Half a second of silence, a quarter second burst, a quarter second of silence.
+    let burst = |seconds: f64, level: f32| vec![level; (seconds * RATE as f64) as usize * 2];
+
+    engine.handle_command(AudioCommand::Start(dir.path().join("burst.wav")));
+    engine.process(&burst(0.5, 0.0));
+    engine.process(&burst(0.25, 0.5));
+    engine.process(&burst(0.25, 0.0));
+    let pending = engine.handle_command(AudioCommand::Stop).unwrap();
+    let points = pending.trim_points().unwrap();
+    // The start backs off 5 ms so the attack survives.
+    assert!((points.start - 0.495).abs() < 1e-3, "{}", points.start);
+    assert!((points.end - 0.75).abs() < 1e-3, "{}", points.end);
+    pending.finish();
+
+    // The history holds the same second of audio.
+    let pending = engine
+        .handle_command(AudioCommand::SaveHistory(dir.path().join("history.wav")))
+        .unwrap();
+    assert_eq!(pending.trim_points(), Some(points));
+    pending.finish();
+
+    // Nothing above the threshold leaves the file untrimmed.
+    engine.handle_command(AudioCommand::Start(dir.path().join("quiet.wav")));
+    engine.process(&burst(0.25, 0.001));
+    let pending = engine.handle_command(AudioCommand::Stop).unwrap();
+    assert_eq!(pending.trim_points(), None);
+    pending.finish();
+}
+
+#[test]
+fn offline_player_renders_until_stopped() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("tone.wav");
+    write_fixture(&path, 1.0, tone);
+
+    let mut player = Player::offline(RATE).unwrap();
+    play(&mut player, &path, StaticSoundSettings::new().volume(0.0));
+    assert!(peak(&player.render(4_800)) > 0.3);
+    assert!(player.is_playing(1));
+
+    player.handle_command(KiraCommand::Stop(1));
+    let after_stop = player.render(4_800);
+    // The 10 ms stop fade is over well before the second half of this block.
+    assert_eq!(peak(&after_stop[after_stop.len() / 2..]), 0.0);
+    assert!(!player.is_playing(1));
+
+    play(&mut player, &path, StaticSoundSettings::new().volume(0.0));
+    player.handle_command(KiraCommand::StopAll);
+    let after_stop_all = player.render(4_800);
+    assert_eq!(peak(&after_stop_all[after_stop_all.len() / 2..]), 0.0);
+    assert!(!player.is_playing(1));
+}
+
+#[test]
+fn start_position_skips_the_trimmed_head() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("late.wav");
+    write_fixture(&path, 1.0, |t| if t < 0.5 { 0.0 } else { tone(t) });
+
+    let mut player = Player::offline(RATE).unwrap();
+    play(&mut player, &path, StaticSoundSettings::new().volume(0.0));
+    assert_eq!(peak(&player.render(2_400)), 0.0);
+
+    play(
+        &mut player,
+        &path,
+        StaticSoundSettings::new().volume(0.0).start_position(0.5),
+    );
+    assert!(peak(&player.render(2_400)) > 0.3);
+}
+
+#[test]
+fn pitch_up_shortens_playback_even_mid_sound() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("short.wav");
+    write_fixture(&path, 0.5, tone);
+
+    let mut player = Player::offline(RATE).unwrap();
+    play(&mut player, &path, StaticSoundSettings::new().volume(0.0));
+    player.render(4_800);
+    // One octave up: the remaining 0.4 s of audio lasts 0.2 s.
+    player.handle_command(KiraCommand::SetPlaybackRate(1, 2.0));
+    player.render(12_000);
+    assert!(!player.is_playing(1));
+    assert_eq!(peak(&player.render(2_400)), 0.0);
+
+    play(&mut player, &path, StaticSoundSettings::new().volume(0.0));
+    player.render(14_400);
+    assert!(player.is_playing(1));
+}
+
+#[test]
+fn loop_region_keeps_playing_past_the_end() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("short.wav");
+    write_fixture(&path, 0.2, tone);
+
+    let mut player = Player::offline(RATE).unwrap();
+    play(
+        &mut player,
+        &path,
+        StaticSoundSettings::new()
+            .volume(0.0)
+            .loop_region(0.05..0.15),
+    );
+    let output = player.render(24_000);
+    assert!(peak(&output[output.len() - 2_400..]) > 0.3);
+    assert!(player.take_finished().is_empty());
+
+    player.handle_command(KiraCommand::Stop(1));
+    player.render(2_400);
+    assert_eq!(player.take_finished(), vec![(1, 0)]);
+    assert!(player.take_finished().is_empty());
+}
+
+#[test]
+fn choke_group_cuts_off_other_members_only() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("tone.wav");
+    write_fixture(&path, 1.0, tone);
+    let settings = StaticSoundSettings::new().volume(0.0);
+
+    let mut player = Player::offline(RATE).unwrap();
+    play_pad(&mut player, 1, Some(1), &path, settings);
+    play_pad(&mut player, 2, None, &path, settings);
+    player.render(2_400);
+    play_pad(&mut player, 3, Some(1), &path, settings);
+    player.render(2_400);
+
+    assert_eq!(player.take_finished(), vec![(1, 0)]);
+    assert!(player.is_playing(2));
+    assert!(player.is_playing(3));
+}
+
+#[test]
+fn retriggers_overlap_up_to_the_voice_limit() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("tone.wav");
+    write_fixture(&path, 1.0, tone);
+    let settings = StaticSoundSettings::new().volume(0.0);
+    let mut player = Player::offline(RATE).unwrap();
+
+    // One voice: the retrigger replaces the first hit, so the level stays that of one voice.
+    play_voices(&mut player, 1, None, 1, &path, settings);
+    player.render(2_400);
+    play_voices(&mut player, 1, None, 1, &path, settings);
+    let single = peak(&player.render(2_400));
+
+    player.handle_command(KiraCommand::Stop(1));
+    player.render(2_400);
+    assert_eq!(player.take_finished(), vec![(1, 0)]);
+
+    // Two voices started together add up; a third hit steals the oldest instead of stacking.
+    play_voices(&mut player, 1, None, 2, &path, settings);
+    play_voices(&mut player, 1, None, 2, &path, settings);
+    let doubled = peak(&player.render(2_400));
+    assert!(doubled > single * 1.5, "{} vs {}", doubled, single);
+    play_voices(&mut player, 1, None, 2, &path, settings);
+    player.render(2_400);
+    assert!(player.take_finished().is_empty());
+
+    // Stop reaches every voice.
+    player.handle_command(KiraCommand::Stop(1));
+    player.render(2_400);
+    assert_eq!(player.take_finished(), vec![(1, 0)]);
+}
+
+#[test]
+fn trimmed_slice_ends_on_time_when_the_rate_drops_mid_play() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("tone.wav");
+    write_fixture(&path, 1.0, tone);
+
+    let mut player = Player::offline(RATE).unwrap();
+    player.handle_command(KiraCommand::Play(KiraPlayRequest {
+        pad_key: 1,
+        sound_data: StaticSoundData::from_file(&path).unwrap().slice(0.0..0.2),
+        settings: StaticSoundSettings::new().volume(0.0),
+        choke_group: None,
+        voices: 1,
+        effects: PadEffects::default(),
+        generation: 0,
+    }));
+    player.render(4_800);
+    // Half speed: the remaining 0.1 s of the slice now lasts 0.2 s.
+    player.handle_command(KiraCommand::SetPlaybackRate(1, 0.5));
+    assert!(peak(&player.render(7_200)) > 0.1);
+    assert!(player.take_finished().is_empty());
+
+    player.render(4_800);
+    assert_eq!(player.take_finished(), vec![(1, 0)]);
+}
+
+#[test]
+fn reverse_plays_the_slice_from_its_end() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("late.wav");
+    write_fixture(&path, 1.0, |t| if t < 0.5 { 0.0 } else { tone(t) });
+
+    let mut player = Player::offline(RATE).unwrap();
+    play(
+        &mut player,
+        &path,
+        StaticSoundSettings::new().volume(0.0).reverse(true),
+    );
+    // Backwards, the tone in the second half comes first and the silence last.
+    assert!(peak(&player.render(4_800)) > 0.3);
+    player.render(24_000);
+    assert_eq!(peak(&player.render(4_800)), 0.0);
+}
+
+/// Zero crossings per second of the left channel, skipping the edges.
+fn frequency(data: &StaticSoundData) -> f64 {
+    let frames = &data.frames[data.frames.len() / 10..data.frames.len() * 9 / 10];
+    let crossings = frames
+        .windows(2)
+        .filter(|pair| (pair[0].left < 0.0) != (pair[1].left < 0.0))
+        .count();
+    crossings as f64 / 2.0 / (frames.len() as f64 / data.sample_rate as f64)
+}
+
+#[test]
+fn stretch_changes_pitch_and_tempo_independently() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("tone.wav");
+    write_fixture(&path, 0.5, tone);
+    let data = StaticSoundData::from_file(&path).unwrap();
+    let params = StretchParams {
+        start_sec: 0.0,
+        end_sec: 0.5,
+        semitones: 0.0,
+        tempo: 1.0,
+    };
+
+    // An octave up keeps the length.
+    let shifted = stretch::render(
+        &data,
+        &StretchParams {
+            semitones: 12.0,
+            ..params
+        },
+    );
+    assert!((shifted.duration().as_secs_f64() - 0.5).abs() < 0.01);
+    assert!(
+        (frequency(&shifted) - 880.0).abs() < 30.0,
+        "{}",
+        frequency(&shifted)
+    );
+
+    // Double tempo halves the length and keeps the pitch.
+    let faster = stretch::render(
+        &data,
+        &StretchParams {
+            tempo: 2.0,
+            ..params
+        },
+    );
+    assert!((faster.duration().as_secs_f64() - 0.25).abs() < 0.01);
+    assert!(
+        (frequency(&faster) - 440.0).abs() < 30.0,
+        "{}",
+        frequency(&faster)
+    );
+
+    // Only the trimmed region is rendered.
+    let trimmed = stretch::render(
+        &data,
+        &StretchParams {
+            start_sec: 0.1,
+            end_sec: 0.3,
+            ..params
+        },
+    );
+    assert!((trimmed.duration().as_secs_f64() - 0.2).abs() < 0.01);
+}
+
+#[test]
+fn envelope_fades_the_region_in_playback_order() {
+    // One second at 1 kHz of full-scale DC, so each frame's level is the envelope gain.
+    let flat = vec![Frame::new(1.0, 1.0); 1000];
+    let fades = Envelope {
+        attack_ms: 100.0,
+        hold_ms: None,
+        release_ms: 200.0,
+    };
+    let mut frames = flat.clone();
+    fades.apply(&mut frames, 1000, false);
+    assert_eq!(frames.len(), 1000);
+    assert!(frames[0].left.abs() < 1e-6);
+    assert!((frames[50].left - 0.5).abs() < 0.02);
+    assert!((frames[500].left - 1.0).abs() < 1e-6);
+    assert!((frames[900].left - 0.5).abs() < 0.02);
+    assert!(frames[999].left < 0.01);
+
+    // A hold cuts the region to attack + hold + release.
+    let held = Envelope {
+        hold_ms: Some(300.0),
+        ..fades
+    };
+    let mut frames = flat.clone();
+    held.apply(&mut frames, 1000, false);
+    assert_eq!(frames.len(), 600);
+    assert!((frames[350].left - 1.0).abs() < 1e-6);
+    assert!((frames[500].left - 0.5).abs() < 0.02);
+
+    // Reversed, the attack sits at the end point and the kept audio is the tail.
+    let mut frames = flat;
+    held.apply(&mut frames, 1000, true);
+    assert_eq!(frames.len(), 600);
+    assert!(frames[599].left.abs() < 1e-6);
+    assert!((frames[549].left - 0.5).abs() < 0.02);
+    assert!(frames[0].left < 0.01);
+}
+
+#[test]
+fn pad_effects_run_on_their_own_track_and_update_live() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("high.wav");
+    write_fixture(&path, 1.0, |t| {
+        (t * 5_000.0 * std::f64::consts::TAU).sin() as f32 * 0.5
+    });
+    let mut player = Player::offline(RATE).unwrap();
+
+    let low_pass = PadEffects {
+        filter: FilterKind::LowPass,
+        cutoff_hz: 200.0,
+        ..PadEffects::default()
+    };
+    player.handle_command(KiraCommand::Play(KiraPlayRequest {
+        pad_key: 1,
+        sound_data: StaticSoundData::from_file(&path).unwrap(),
+        settings: StaticSoundSettings::new(),
+        choke_group: None,
+        voices: 1,
+        effects: low_pass,
+        generation: 0,
+    }));
+    // Skip the filter settling, then the 5 kHz tone is mostly gone.
+    player.render(4_800);
+    assert!(peak(&player.render(4_800)) < 0.1);
+
+    player.handle_command(KiraCommand::SetEffects(1, PadEffects::default()));
+    player.render(4_800);
+    assert!(peak(&player.render(4_800)) > 0.4);
+}
+
+#[test]
+fn master_limiter_holds_stacked_pads_under_full_scale() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("tone.wav");
+    write_fixture(&path, 1.0, tone);
+    let mut player = Player::offline(RATE).unwrap();
+
+    // Two pads at +15 dB would peak near 5.6 without the limiter.
+    play_pad(
+        &mut player,
+        1,
+        None,
+        &path,
+        StaticSoundSettings::new().volume(15.0),
+    );
+    play_pad(
+        &mut player,
+        2,
+        None,
+        &path,
+        StaticSoundSettings::new().volume(15.0),
+    );
+    let loud = player.render(4_800);
+    assert!(peak(&loud) <= 1.0, "{}", peak(&loud));
+    assert!(peak(&loud) > 0.9);
+    assert!(player.master().reduction_db() > 10.0);
+
+    // Master gain comes before the limiter: turned down, nothing needs limiting.
+    player.master().set_gain_db(-40.0);
+    player.render(4_800);
+    let quiet = player.render(4_800);
+    assert!(peak(&quiet) < 0.1);
+    assert!(player.master().reduction_db() < 0.1);
+}
+
+#[test]
+fn meters_follow_the_master_and_each_pad() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("tone.wav");
+    write_fixture(&path, 1.0, tone);
+    let mut player = Player::offline(RATE).unwrap();
+
+    play_pad(&mut player, 1, None, &path, StaticSoundSettings::new());
+    player.render(36_000);
+    let meters = player.meters().clone();
+    // A 0.5 sine: peak 0.5, RMS 0.35.
+    assert!((meters.pad(1).peak() - 0.5).abs() < 0.02);
+    assert!((meters.pad(1).rms() - 0.354).abs() < 0.03);
+    assert!((meters.master.peak() - 0.5).abs() < 0.02);
+    assert_eq!(meters.pad(2).peak(), 0.0);
+
+    // After the sound ends the meters fall back.
+    player.render(144_000);
+    assert!(meters.pad(1).peak() < 0.01);
+    assert!(meters.master.rms() < 0.01);
+}
+
+#[test]
+fn input_monitor_tracks_level_columns_and_clipping_while_recording() {
+    let dir = TempDir::new().unwrap();
+    let monitor = Arc::new(InputMonitor::default());
+    let mut engine = CaptureEngine::new(
+        CaptureConfig {
+            pre_buffer_seconds: 0.0,
+            gain: 2.0,
+            clip_mode: InputClipMode::Clip,
+            ..CaptureConfig::default()
+        },
+        monitor.clone(),
+    );
+    engine.set_format(CaptureFormat {
+        rate: RATE,
+        channels: 2,
+    });
+
+    // Listening: the level moves but nothing scrolls.
+    engine.process(&vec![0.25; 4_800]);
+    assert!((monitor.level.peak() - 0.5).abs() < 1e-6);
+    assert!(monitor.recent_peaks(960).is_empty());
+
+    engine.handle_command(AudioCommand::Start(dir.path().join("pad.wav")));
+    // 0.1 s quiet, then 0.1 s that the gain pushes past full scale.
+    engine.process(&vec![0.1; 9_600]);
+    assert!(!monitor.is_clipped());
+    engine.process(&vec![0.6; 9_600]);
+
+    let peaks = monitor.recent_peaks(960);
+    assert_eq!(peaks.len(), 20);
+    assert!((peaks[0] - 0.2).abs() < 1e-6);
+    assert!((peaks[19] - 1.2).abs() < 1e-6);
+    assert!(monitor.is_clipped());
+    assert!((monitor.elapsed().as_secs_f64() - 0.2).abs() < 1e-6);
+
+    // A new recording starts from scratch.
+    engine.handle_command(AudioCommand::Stop);
+    engine.handle_command(AudioCommand::Start(dir.path().join("pad.wav")));
+    assert!(monitor.recent_peaks(960).is_empty());
+    assert!(!monitor.is_clipped());
+}
+
+#[test]
+fn playhead_follows_rate_changes_and_reverse() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("tone.wav");
+    write_fixture(&path, 1.0, tone);
+    let slice = || StaticSoundData::from_file(&path).unwrap().slice(0.2..0.8);
+    let mut player = Player::offline(RATE).unwrap();
+
+    player.handle_command(KiraCommand::Play(KiraPlayRequest {
+        pad_key: 1,
+        sound_data: slice(),
+        settings: StaticSoundSettings::new(),
+        choke_group: None,
+        voices: 1,
+        effects: PadEffects::default(),
+        generation: 0,
+    }));
+    player.handle_command(KiraCommand::Play(KiraPlayRequest {
+        pad_key: 2,
+        sound_data: slice(),
+        settings: StaticSoundSettings::new().reverse(true),
+        choke_group: None,
+        voices: 1,
+        effects: PadEffects::default(),
+        generation: 0,
+    }));
+    player.render(4_800);
+    player.handle_command(KiraCommand::SetPlaybackRate(1, 2.0));
+    player.render(4_800);
+
+    let playheads: std::collections::HashMap<u8, f64> = player.playheads().into_iter().collect();
+    // 0.1 s at normal speed, then 0.1 s at double speed.
+    assert!((playheads[&1] - 0.3).abs() < 0.01, "{}", playheads[&1]);
+    // Reversed voices report time played, not distance from the slice start.
+    assert!((playheads[&2] - 0.2).abs() < 0.01, "{}", playheads[&2]);
+}
+
+/// A playback node with ports 11/12 and a stereo sink with ports 21/22.
+fn routing_graph() -> Graph {
+    let mut graph = Graph::default();
+    graph.add_node(1, "pushboard");
+    graph.add_port(11, 1, true, Some("FL"));
+    graph.add_port(12, 1, true, Some("FR"));
+    graph.add_node(2, "speakers");
+    graph.add_port(21, 2, false, Some("FL"));
+    graph.add_port(22, 2, false, Some("FR"));
+    graph
+}
+
+#[test]
+fn routing_plans_links_as_targets_come_and_go() {
+    let speakers = [("speakers".to_string(), true)];
+    let mut graph = routing_graph();
+    let plan = graph.plan("pushboard", &speakers);
+    assert_eq!(plan.create, [(11, 21), (12, 22)]);
+    assert!(plan.waiting);
+
+    // Links that already exist are left alone.
+    graph.add_link(31, (11, 21));
+    graph.add_link(32, (12, 22));
+    assert_eq!(graph.plan("pushboard", &speakers), LinkPlan::default());
+
+    // The sink is unplugged, taking its ports and links with it.
+    for id in [31, 32, 21, 22, 2] {
+        assert!(graph.remove(id));
+    }
+    let plan = graph.plan("pushboard", &speakers);
+    assert_eq!(plan.missing, ["speakers"]);
+    assert!(plan.create.is_empty());
+
+    // Plugged back in under new ids, it is linked again.
+    graph.add_node(3, "speakers");
+    let plan = graph.plan("pushboard", &speakers);
+    assert!(plan.waiting && plan.create.is_empty());
+    graph.add_port(41, 3, false, Some("FL"));
+    graph.add_port(42, 3, false, Some("FR"));
+    assert_eq!(
+        graph.plan("pushboard", &speakers).create,
+        [(11, 41), (12, 42)]
+    );
+
+    // A target that is no longer wanted has its links destroyed.
+    graph.add_link(51, (11, 41));
+    graph.add_link(52, (12, 42));
+    let plan = graph.plan("pushboard", &[("speakers".to_string(), false)]);
+    assert_eq!(plan.destroy, [51, 52]);
+}
+
+#[test]
+fn failed_links_wait_for_their_nodes_to_change() {
+    let speakers = [("speakers".to_string(), true)];
+    let mut graph = routing_graph();
+    graph.mark_failed((11, 21), "refused".to_string());
+    let plan = graph.plan("pushboard", &speakers);
+    assert_eq!(plan.create, [(12, 22)]);
+    assert_eq!(plan.failed.as_deref(), Some("refused"));
+
+    // Unrelated nodes don't trigger a retry.
+    graph.add_node(5, "mixer");
+    assert_eq!(graph.plan("pushboard", &speakers).create, [(12, 22)]);
+
+    // A new port on the sink does.
+    graph.add_port(23, 2, false, Some("LFE"));
+    let plan = graph.plan("pushboard", &speakers);
+    assert_eq!(plan.create, [(11, 21), (12, 22)]);
+    assert_eq!(plan.failed, None);
+}
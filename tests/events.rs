@@ -0,0 +1,1097 @@
+use embedded_graphics::pixelcolor::{Bgr565, RgbColor};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use push2::PadCoord;
+use push2::{ControlName, EncoderName};
+use pushboard::app::pad_settings::{PadMode, PitchMode};
+use pushboard::app::render::RENDER_SETTLE_DELAY;
+use pushboard::app::state::{AppCommand, AppState, AudioCommand, EditPage};
+use pushboard::app::velocity::VelocityCurve;
+use pushboard::app::{events, initial_hardware_setup, ui};
+use pushboard::audio::capture::gain::InputClipMode;
+use pushboard::audio::capture::{CaptureEngine, CaptureFormat};
+use pushboard::audio::effects::FilterKind;
+use pushboard::audio::player::KiraCommand;
+use pushboard::audio::routing::PlaybackSink;
+use pushboard::audio::trim::TrimPoints;
+use pushboard::config::Config;
+use pushboard::hardware::controller::Controller;
+use pushboard::hardware::virtual_push2::VirtualPush2;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+use tempfile::TempDir;
+
+struct Harness {
+    push2: VirtualPush2,
+    state: AppState,
+    audio_rx: Receiver<AudioCommand>,
+    kira_rx: Receiver<KiraCommand>,
+    app_tx: Sender<AppCommand>,
+    app_rx: Receiver<AppCommand>,
+    _routing_rx: pipewire::channel::Receiver<PlaybackSink>,
+    storage: TempDir,
+}
+
+impl Harness {
+    fn new() -> Self {
+        Self::with_storage(TempDir::new().unwrap())
+    }
+
+    fn with_storage(storage: TempDir) -> Self {
+        let mut config = Config::default();
+        config.storage_dir = Some(storage.path().to_path_buf());
+
+        let (audio_tx, audio_rx) = mpsc::channel();
+        let (kira_tx, kira_rx) = mpsc::channel();
+        let (app_tx, app_rx) = mpsc::channel();
+        let (routing_tx, routing_rx) = pipewire::channel::channel();
+
+        let mut push2 = VirtualPush2::new();
+        let mut state =
+            AppState::new(config, audio_tx, kira_tx, app_tx.clone(), routing_tx).unwrap();
+        initial_hardware_setup(&mut push2, &mut state).unwrap();
+
+        Self {
+            push2,
+            state,
+            audio_rx,
+            kira_rx,
+            app_tx,
+            app_rx,
+            _routing_rx: routing_rx,
+            storage,
+        }
+    }
+
+    async fn step(&mut self) {
+        events::handle_incoming_events(&mut self.push2, &mut self.state, &self.app_rx)
+            .await
+            .unwrap();
+    }
+
+    /// Steps until the pad's background render has come back.
+    async fn wait_for_render(&mut self, key: u8) {
+        for _ in 0..500 {
+            self.step().await;
+            if self.state.render_cache.contains_key(&key) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("pad {} was never rendered", key);
+    }
+
+    fn draw(&mut self) {
+        ui::draw_screen(&mut self.push2, &mut self.state).unwrap();
+    }
+
+    fn pad_path(&self, x: u8, y: u8) -> PathBuf {
+        self.state.bank_path().join(format!("pad_{}_{}.wav", x, y))
+    }
+
+    fn address(&self, x: u8, y: u8) -> u8 {
+        self.push2.pad_address(PadCoord { x, y }).unwrap()
+    }
+
+    /// Writes a short stereo sine to the pad's file and refreshes the LEDs.
+    fn write_sample(&mut self, x: u8, y: u8) {
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 48_000,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut writer = WavWriter::create(self.pad_path(x, y), spec).unwrap();
+        for n in 0..24_000 {
+            let sample = (n as f32 * 440.0 * std::f32::consts::TAU / 48_000.0).sin() * 0.5;
+            writer.write_sample(sample).unwrap();
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        self.state.update_pad_lights(&mut self.push2).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn empty_pad_records_while_held() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    assert_eq!(h.push2.pad_color(2, 3), colors.off);
+
+    h.push2.press_pad(2, 3, 100);
+    h.step().await;
+    match h.audio_rx.try_recv() {
+        Ok(AudioCommand::Start(path)) => assert_eq!(path, h.pad_path(2, 3)),
+        other => panic!("expected Start, got {:?}", other),
+    }
+    assert_eq!(h.push2.pad_color(2, 3), colors.recording);
+
+    h.push2.release_pad(2, 3);
+    h.step().await;
+    assert!(matches!(h.audio_rx.try_recv(), Ok(AudioCommand::Stop)));
+    assert_eq!(h.push2.pad_color(2, 3), colors.has_file);
+    assert_eq!(h.state.active_recording_key, None);
+}
+
+#[tokio::test]
+async fn recording_that_hits_the_length_limit_ends_on_its_own() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    let key = h.address(4, 3);
+    h.push2.press_pad(4, 3, 100);
+    h.step().await;
+    assert_eq!(h.state.active_recording_key, Some(key));
+
+    // The capture thread stopped at the limit and saved while the pad is still held.
+    h.write_sample(4, 3);
+    h.app_tx
+        .send(AppCommand::FileSaved(h.pad_path(4, 3), None))
+        .unwrap();
+    h.step().await;
+    assert_eq!(h.state.active_recording_key, None);
+    assert_eq!(h.state.selected_for_edit, Some(key));
+    assert_eq!(h.push2.pad_color(4, 3), colors.selected);
+
+    // Letting go afterwards doesn't send a stray Stop.
+    let _ = h.audio_rx.try_recv();
+    h.push2.release_pad(4, 3);
+    h.step().await;
+    assert!(h.audio_rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn capture_and_empty_pad_saves_the_input_history_for_trimming() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    let key = h.address(5, 5);
+    h.push2.press_button(ControlName::Clip);
+    h.push2.press_button(ControlName::Capture);
+    h.push2.press_pad(5, 5, 100);
+    h.push2.release_pad(5, 5);
+    h.push2.release_button(ControlName::Capture);
+    h.step().await;
+    match h.audio_rx.try_recv() {
+        Ok(AudioCommand::SaveHistory(path)) => assert_eq!(path, h.pad_path(5, 5)),
+        other => panic!("expected SaveHistory, got {:?}", other),
+    }
+    // Nothing else: no recording is started or stopped.
+    assert!(h.audio_rx.try_recv().is_err());
+    assert_eq!(h.state.active_recording_key, None);
+    assert_eq!(h.push2.pad_color(5, 5), colors.recording);
+    assert_eq!(h.state.edit_page, EditPage::Sample);
+
+    h.write_sample(5, 5);
+    h.app_tx
+        .send(AppCommand::FileSaved(h.pad_path(5, 5), None))
+        .unwrap();
+    h.step().await;
+    assert_eq!(h.state.selected_for_edit, Some(key));
+    assert_eq!(h.push2.pad_color(5, 5), colors.selected);
+
+    // A pad that already has a sample is left alone.
+    h.push2.press_button(ControlName::Capture);
+    h.push2.press_pad(5, 5, 100);
+    h.step().await;
+    assert!(h.audio_rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn shift_and_empty_pad_arms_a_recording_that_ends_on_a_second_press() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    let key = h.address(6, 6);
+    let mut engine = CaptureEngine::new(h.state.config.capture, h.state.input_monitor.clone());
+    engine.set_format(CaptureFormat {
+        rate: 48_000,
+        channels: 2,
+    });
+
+    h.push2.press_button(ControlName::Shift);
+    h.push2.press_pad(6, 6, 100);
+    h.push2.release_button(ControlName::Shift);
+    h.push2.release_pad(6, 6);
+    h.step().await;
+    let Ok(arm) = h.audio_rx.try_recv() else {
+        panic!("expected Arm");
+    };
+    assert!(matches!(&arm, AudioCommand::Arm(path) if *path == h.pad_path(6, 6)));
+    engine.handle_command(arm);
+    // Letting go of the pad doesn't stop an armed recording.
+    assert!(h.audio_rx.try_recv().is_err());
+    assert_eq!(h.state.active_recording_key, Some(key));
+    assert_eq!(h.push2.pad_color(6, 6), colors.recording);
+
+    // Pressed again before anything was loud enough: disarmed, and the pad is still empty.
+    h.push2.press_pad(6, 6, 100);
+    h.step().await;
+    assert!(matches!(h.audio_rx.try_recv(), Ok(AudioCommand::Stop)));
+    assert_eq!(h.state.active_recording_key, None);
+    assert!(!h.state.is_recording_armed);
+    assert_eq!(h.push2.pad_color(6, 6), colors.off);
+}
+
+#[tokio::test]
+async fn pad_with_sample_plays_and_selects() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    h.write_sample(0, 0);
+    assert_eq!(h.push2.pad_color(0, 0), colors.has_file);
+
+    h.push2.press_pad(0, 0, 127);
+    h.step().await;
+    match h.kira_rx.try_recv() {
+        Ok(KiraCommand::Play(req)) => assert_eq!(req.pad_key, h.address(0, 0)),
+        other => panic!("expected Play, got {:?}", other),
+    }
+    assert_eq!(h.push2.pad_color(0, 0), colors.playing);
+    assert_eq!(h.state.selected_for_edit, Some(h.address(0, 0)));
+
+    h.push2.release_pad(0, 0);
+    h.step().await;
+    assert_eq!(h.push2.pad_color(0, 0), colors.selected);
+}
+
+#[tokio::test]
+async fn select_modifier_selects_without_playing() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    h.write_sample(4, 4);
+    h.write_sample(5, 5);
+
+    h.push2.press_button(ControlName::Select);
+    h.push2.press_pad(4, 4, 100);
+    h.push2.press_pad(5, 5, 100);
+    h.step().await;
+
+    assert!(h.kira_rx.try_recv().is_err());
+    assert_eq!(h.state.selected_for_edit, Some(h.address(5, 5)));
+    assert_eq!(h.push2.pad_color(4, 4), colors.has_file);
+    assert_eq!(h.push2.pad_color(5, 5), colors.selected);
+    assert_eq!(h.push2.button_light(ControlName::Select), colors.button_on);
+
+    h.push2.release_button(ControlName::Select);
+    h.step().await;
+    assert_eq!(h.push2.button_light(ControlName::Select), 0);
+}
+
+#[tokio::test]
+async fn encoder_edits_reach_engine_display_and_disk() {
+    let mut h = Harness::new();
+    h.write_sample(1, 1);
+    let key = h.address(1, 1);
+
+    h.push2.press_button(ControlName::Select);
+    h.push2.press_pad(1, 1, 100);
+    h.push2.release_button(ControlName::Select);
+    h.push2.twist_encoder(EncoderName::Track1, 10);
+    h.push2.twist_encoder(EncoderName::Track3, -5);
+    h.step().await;
+
+    match h.kira_rx.try_recv() {
+        Ok(KiraCommand::SetVolume(k, volume)) => {
+            assert_eq!(k, key);
+            assert!((volume - 2.0).abs() < 1e-9);
+        }
+        other => panic!("expected SetVolume, got {:?}", other),
+    }
+    // Start point cannot go below zero.
+    assert_eq!(h.state.pad_settings.get(key).start_point, 0.0);
+
+    h.draw();
+    let volume_bar = h.push2.encoder(0).bar.expect("volume bar drawn");
+    assert!(volume_bar.0 > 0);
+    assert!(h.push2.flush_count() > 0);
+
+    tokio::time::sleep(Duration::from_millis(800)).await;
+    h.step().await;
+    let saved = std::fs::read_to_string(h.storage.path().join("bank_01/pads.json")).unwrap();
+    assert!(saved.contains("\"volume\": 2.0"), "{}", saved);
+}
+
+#[tokio::test]
+async fn delete_modifier_removes_sample() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    h.write_sample(6, 2);
+    let path = h.pad_path(6, 2);
+
+    h.push2.press_button(ControlName::Delete);
+    h.push2.press_pad(6, 2, 100);
+    h.step().await;
+    assert_eq!(h.push2.pad_color(6, 2), colors.off);
+
+    for _ in 0..50 {
+        if !path.exists() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn armed_recording_that_cannot_start_frees_the_pad() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    h.push2.press_button(ControlName::Shift);
+    h.push2.press_pad(1, 6, 100);
+    h.push2.release_pad(1, 6);
+    h.push2.release_button(ControlName::Shift);
+    h.step().await;
+    assert!(matches!(h.audio_rx.try_recv(), Ok(AudioCommand::Arm(_))));
+    assert!(h.state.is_recording_armed);
+
+    h.app_tx
+        .send(AppCommand::RecordingFailed(h.pad_path(1, 6)))
+        .unwrap();
+    h.step().await;
+    assert_eq!(h.state.active_recording_key, None);
+    assert!(!h.state.is_recording_armed);
+    assert_eq!(h.push2.pad_color(1, 6), colors.off);
+}
+
+#[tokio::test]
+async fn empty_pad_does_not_record_while_another_is_armed() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    let armed = h.address(1, 6);
+    h.push2.press_button(ControlName::Shift);
+    h.push2.press_pad(1, 6, 100);
+    h.push2.release_pad(1, 6);
+    h.push2.release_button(ControlName::Shift);
+    h.step().await;
+    assert!(matches!(h.audio_rx.try_recv(), Ok(AudioCommand::Arm(_))));
+
+    h.push2.press_pad(2, 6, 100);
+    h.push2.release_pad(2, 6);
+    h.step().await;
+    assert!(h.audio_rx.try_recv().is_err());
+    assert_eq!(h.state.active_recording_key, Some(armed));
+    assert!(h.state.is_recording_armed);
+    assert_eq!(h.push2.pad_color(1, 6), colors.recording);
+    assert_eq!(h.push2.pad_color(2, 6), colors.off);
+}
+
+#[tokio::test]
+async fn saved_recording_is_selected_for_editing() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    h.write_sample(3, 7);
+
+    h.app_tx
+        .send(AppCommand::FileSaved(h.pad_path(3, 7), None))
+        .unwrap();
+    h.step().await;
+
+    assert_eq!(h.state.selected_for_edit, Some(h.address(3, 7)));
+    assert_eq!(h.push2.pad_color(3, 7), colors.selected);
+}
+
+#[tokio::test]
+async fn saved_recording_is_trimmed_and_undo_restores_the_full_file() {
+    let mut h = Harness::new();
+    let key = h.address(2, 6);
+    h.write_sample(2, 6);
+
+    let trim = TrimPoints {
+        start: 0.245,
+        end: 0.75,
+    };
+    h.app_tx
+        .send(AppCommand::FileSaved(h.pad_path(2, 6), Some(trim)))
+        .unwrap();
+    h.step().await;
+    let pad = h.state.pad_settings.get(key);
+    assert_eq!(pad.start_point, 0.245);
+    assert_eq!(pad.end_point, 0.75);
+
+    h.push2.press_button(ControlName::Undo);
+    h.step().await;
+    let pad = h.state.pad_settings.get(key);
+    assert_eq!(pad.start_point, 0.0);
+    assert_eq!(pad.end_point, 1.0);
+}
+
+#[tokio::test]
+async fn mute_button_toggles_its_light() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    assert_eq!(h.push2.button_light(ControlName::Mute), colors.button_on);
+
+    h.push2.press_button(ControlName::Mute);
+    h.step().await;
+    assert!(!h.state.is_mute_enabled);
+    assert_eq!(h.push2.button_light(ControlName::Mute), 0);
+}
+
+#[tokio::test]
+async fn page_buttons_switch_banks() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    h.write_sample(0, 0);
+    assert_eq!(h.push2.button_light(ControlName::PageLeft), 0);
+    assert_eq!(
+        h.push2.button_light(ControlName::PageRight),
+        colors.button_on
+    );
+
+    // A one-shot still sounding from this bank is stopped along with everything else.
+    h.push2.press_pad(0, 0, 100);
+    h.push2.release_pad(0, 0);
+    h.step().await;
+    assert!(matches!(h.kira_rx.try_recv(), Ok(KiraCommand::Play(_))));
+
+    h.push2.press_button(ControlName::PageRight);
+    h.step().await;
+    assert_eq!(h.state.current_bank, 1);
+    assert!(matches!(h.kira_rx.try_recv(), Ok(KiraCommand::StopAll)));
+    assert!(h.state.playing_pads.is_empty());
+    assert_eq!(h.push2.pad_color(0, 0), colors.off);
+    assert_eq!(
+        h.push2.button_light(ControlName::PageLeft),
+        colors.button_on
+    );
+
+    // The empty pad records into the second bank's directory.
+    h.push2.press_pad(0, 0, 100);
+    h.step().await;
+    match h.audio_rx.try_recv() {
+        Ok(AudioCommand::Start(path)) => {
+            assert_eq!(path, h.storage.path().join("bank_02/pad_0_0.wav"))
+        }
+        other => panic!("expected Start, got {:?}", other),
+    }
+    h.push2.release_pad(0, 0);
+    h.step().await;
+
+    h.push2.press_button(ControlName::PageLeft);
+    h.step().await;
+    assert_eq!(h.state.current_bank, 0);
+    assert_eq!(h.push2.pad_color(0, 0), colors.has_file);
+
+    h.push2.press_button(ControlName::OctaveDown);
+    h.step().await;
+    assert_eq!(h.state.current_bank, 0);
+}
+
+#[tokio::test]
+async fn samples_from_before_banks_move_into_the_first_bank() {
+    let storage = TempDir::new().unwrap();
+    std::fs::write(storage.path().join("pad_2_2.wav"), b"").unwrap();
+    std::fs::write(storage.path().join("pads.json"), "{}").unwrap();
+    let mut config = Config::default();
+    config.storage_dir = Some(storage.path().to_path_buf());
+
+    let (audio_tx, _audio_rx) = mpsc::channel();
+    let (kira_tx, _kira_rx) = mpsc::channel();
+    let (app_tx, _app_rx) = mpsc::channel();
+    let (routing_tx, _routing_rx) = pipewire::channel::channel();
+    AppState::new(config, audio_tx, kira_tx, app_tx, routing_tx).unwrap();
+
+    assert!(storage.path().join("bank_01/pad_2_2.wav").exists());
+    assert!(storage.path().join("bank_01/pads.json").exists());
+    assert!(!storage.path().join("pad_2_2.wav").exists());
+}
+
+#[tokio::test]
+async fn recording_left_behind_by_a_crash_is_recovered_on_startup() {
+    let storage = TempDir::new().unwrap();
+    let bank_path = storage.path().join("bank_02");
+    std::fs::create_dir_all(&bank_path).unwrap();
+    let part = bank_path.join("pad_1_1.wav.part");
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 48_000,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut writer = WavWriter::create(&part, spec).unwrap();
+    for _ in 0..4_800 * 2 {
+        writer.write_sample(0.25_f32).unwrap();
+    }
+    writer.finalize().unwrap();
+    // Audio written after the last header update, ending in a torn sample.
+    let mut tail: Vec<u8> = (0..2_400 * 2)
+        .flat_map(|_| 0.25_f32.to_le_bytes())
+        .collect();
+    tail.extend_from_slice(&[0; 3]);
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&part)
+        .unwrap();
+    std::io::Write::write_all(&mut file, &tail).unwrap();
+    drop(file);
+
+    let mut h = Harness::with_storage(storage);
+    let colors = h.state.config.colors;
+    assert!(!part.exists());
+    let reader = hound::WavReader::open(bank_path.join("pad_1_1.wav")).unwrap();
+    assert_eq!(reader.duration(), 7_200);
+
+    h.push2.press_button(ControlName::PageRight);
+    h.step().await;
+    assert_eq!(h.push2.pad_color(1, 1), colors.has_file);
+}
+
+#[tokio::test]
+async fn gate_pad_stops_on_release() {
+    let mut h = Harness::new();
+    h.write_sample(2, 0);
+    let key = h.address(2, 0);
+    h.state.pad_settings.get_mut(key).mode = PadMode::Gate;
+
+    h.push2.press_pad(2, 0, 100);
+    h.step().await;
+    assert!(matches!(h.kira_rx.try_recv(), Ok(KiraCommand::Play(_))));
+
+    h.push2.release_pad(2, 0);
+    h.step().await;
+    assert!(matches!(h.kira_rx.try_recv(), Ok(KiraCommand::Stop(k)) if k == key));
+    assert!(!h.state.playing_pads.contains(&key));
+}
+
+#[tokio::test]
+async fn toggle_pad_stays_lit_until_pressed_again() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    h.write_sample(3, 0);
+    let key = h.address(3, 0);
+    h.state.pad_settings.get_mut(key).mode = PadMode::Toggle;
+
+    h.push2.press_pad(3, 0, 100);
+    h.push2.release_pad(3, 0);
+    h.step().await;
+    assert!(matches!(h.kira_rx.try_recv(), Ok(KiraCommand::Play(_))));
+    assert!(h.kira_rx.try_recv().is_err());
+    assert_eq!(h.push2.pad_color(3, 0), colors.playing);
+
+    h.push2.press_pad(3, 0, 100);
+    h.step().await;
+    assert!(matches!(h.kira_rx.try_recv(), Ok(KiraCommand::Stop(k)) if k == key));
+    assert_eq!(h.push2.pad_color(3, 0), colors.selected);
+}
+
+#[tokio::test]
+async fn loop_pad_repeats_between_trim_points() {
+    let mut h = Harness::new();
+    h.write_sample(4, 0);
+    let key = h.address(4, 0);
+    h.state.pad_settings.get_mut(key).mode = PadMode::Loop;
+
+    h.push2.press_pad(4, 0, 100);
+    h.step().await;
+    match h.kira_rx.try_recv() {
+        Ok(KiraCommand::Play(req)) => assert!(req.settings.loop_region.is_some()),
+        other => panic!("expected Play, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn finished_playback_restores_the_pad_color() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    h.write_sample(5, 0);
+    let key = h.address(5, 0);
+    h.state.pad_settings.get_mut(key).mode = PadMode::Toggle;
+    h.push2.press_pad(5, 0, 100);
+    h.push2.release_pad(5, 0);
+    h.step().await;
+    assert_eq!(h.push2.pad_color(5, 0), colors.playing);
+
+    let generation = h.state.play_generations[&key];
+    h.app_tx
+        .send(AppCommand::PlaybackFinished(key, generation))
+        .unwrap();
+    h.step().await;
+    assert!(!h.state.playing_pads.contains(&key));
+    assert_eq!(h.push2.pad_color(5, 0), colors.selected);
+}
+
+#[tokio::test]
+async fn stale_finish_report_does_not_stop_a_retriggered_pad() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    h.write_sample(5, 0);
+    let key = h.address(5, 0);
+    h.push2.press_pad(5, 0, 100);
+    h.step().await;
+    let first = h.state.play_generations[&key];
+
+    // The first play ends in the player while the retrigger is on its way there.
+    h.push2.press_pad(5, 0, 100);
+    h.step().await;
+    h.app_tx
+        .send(AppCommand::PlaybackFinished(key, first))
+        .unwrap();
+    h.step().await;
+    assert!(h.state.playing_pads.contains(&key));
+    assert_eq!(h.push2.pad_color(5, 0), colors.playing);
+
+    let second = h.state.play_generations[&key];
+    assert_ne!(first, second);
+    h.app_tx
+        .send(AppCommand::PlaybackFinished(key, second))
+        .unwrap();
+    h.step().await;
+    assert!(!h.state.playing_pads.contains(&key));
+}
+
+#[tokio::test]
+async fn mode_encoder_steps_through_modes() {
+    let mut h = Harness::new();
+    h.write_sample(6, 0);
+    let key = h.address(6, 0);
+    h.push2.press_button(ControlName::Select);
+    h.push2.press_pad(6, 0, 100);
+    h.push2.release_button(ControlName::Select);
+
+    // A few ticks are not enough to change a list parameter.
+    h.push2.twist_encoder(EncoderName::Track5, 3);
+    h.step().await;
+    assert_eq!(h.state.pad_settings.get(key).mode, PadMode::OneShot);
+
+    h.push2.twist_encoder(EncoderName::Track5, 5);
+    h.step().await;
+    assert_eq!(h.state.pad_settings.get(key).mode, PadMode::Gate);
+
+    h.push2.twist_encoder(EncoderName::Track5, 40);
+    h.step().await;
+    assert_eq!(h.state.pad_settings.get(key).mode, PadMode::Loop);
+}
+
+#[tokio::test]
+async fn choke_group_is_edited_and_sent_with_play() {
+    let mut h = Harness::new();
+    h.write_sample(7, 0);
+    let key = h.address(7, 0);
+    h.push2.press_button(ControlName::Select);
+    h.push2.press_pad(7, 0, 100);
+    h.push2.release_button(ControlName::Select);
+    h.push2.twist_encoder(EncoderName::Track6, 16);
+    h.step().await;
+    assert_eq!(h.state.pad_settings.get(key).choke_group, Some(2));
+
+    h.push2.press_pad(7, 0, 100);
+    h.step().await;
+    match h.kira_rx.try_recv() {
+        Ok(KiraCommand::Play(req)) => assert_eq!(req.choke_group, Some(2)),
+        other => panic!("expected Play, got {:?}", other),
+    }
+
+    h.push2.twist_encoder(EncoderName::Track6, -40);
+    h.step().await;
+    assert_eq!(h.state.pad_settings.get(key).choke_group, None);
+}
+
+#[tokio::test]
+async fn velocity_sets_gain_through_global_and_pad_curves() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    h.write_sample(0, 7);
+    let key = h.address(0, 7);
+    assert_eq!(h.state.velocity_curve, VelocityCurve::Linear);
+    assert_eq!(h.push2.button_light(ControlName::Accent), colors.button_on);
+
+    h.push2.press_pad(0, 7, 127);
+    h.step().await;
+    assert!(h.state.velocity_gain_db[&key].abs() < 1e-9);
+
+    h.push2.press_pad(0, 7, 64);
+    h.step().await;
+    let linear_soft = h.state.velocity_gain_db[&key];
+    assert!((linear_soft - 20.0 * (64.0_f64 / 127.0).log10()).abs() < 1e-9);
+
+    h.push2.press_button(ControlName::Accent);
+    h.push2.press_pad(0, 7, 64);
+    h.step().await;
+    assert_eq!(h.state.velocity_curve, VelocityCurve::Logarithmic);
+    assert!(h.state.velocity_gain_db[&key] < linear_soft);
+
+    h.state.pad_settings.get_mut(key).velocity_curve = Some(VelocityCurve::Fixed);
+    h.push2.press_pad(0, 7, 10);
+    h.step().await;
+    assert_eq!(h.state.velocity_gain_db[&key], 0.0);
+}
+
+#[tokio::test]
+async fn voice_count_is_edited_and_sent_with_play() {
+    let mut h = Harness::new();
+    h.write_sample(1, 7);
+    let key = h.address(1, 7);
+    assert_eq!(h.state.pad_settings.get(key).voices, 1);
+
+    h.push2.press_button(ControlName::Select);
+    h.push2.press_pad(1, 7, 100);
+    h.push2.release_button(ControlName::Select);
+    h.push2.twist_encoder(EncoderName::Track8, 24);
+    h.step().await;
+    assert_eq!(h.state.pad_settings.get(key).voices, 4);
+
+    h.push2.press_pad(1, 7, 100);
+    h.step().await;
+    match h.kira_rx.try_recv() {
+        Ok(KiraCommand::Play(req)) => assert_eq!(req.voices, 4),
+        other => panic!("expected Play, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn play_request_contains_only_the_trimmed_audio() {
+    let mut h = Harness::new();
+    h.write_sample(2, 7);
+    let key = h.address(2, 7);
+    let pad = h.state.pad_settings.get_mut(key);
+    pad.start_point = 0.25;
+    pad.end_point = 0.5;
+
+    h.push2.press_pad(2, 7, 100);
+    h.step().await;
+    match h.kira_rx.try_recv() {
+        Ok(KiraCommand::Play(req)) => {
+            // A quarter of the 0.5 s sample
+            let seconds = req.sound_data.duration().as_secs_f64();
+            assert!((seconds - 0.125).abs() < 1e-3, "{}", seconds);
+        }
+        other => panic!("expected Play, got {:?}", other),
+    }
+
+    // Collapsed trim points play nothing rather than the whole file.
+    h.state.pad_settings.get_mut(key).end_point = 0.25;
+    h.push2.press_pad(2, 7, 100);
+    h.step().await;
+    assert!(h.kira_rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn convert_button_reverses_the_selected_pad() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    h.write_sample(3, 6);
+    let key = h.address(3, 6);
+
+    h.push2.press_button(ControlName::Select);
+    h.push2.press_pad(3, 6, 100);
+    h.push2.release_button(ControlName::Select);
+    h.step().await;
+    h.draw();
+    // Forward: the arrow sits just right of the start line.
+    assert_eq!(h.push2.screen().pixel(3, 80), Some(Bgr565::WHITE));
+    assert_eq!(h.push2.button_light(ControlName::Convert), 0);
+
+    h.push2.press_button(ControlName::Convert);
+    h.push2.press_pad(3, 6, 100);
+    h.step().await;
+    assert!(h.state.pad_settings.get(key).reverse);
+    assert_eq!(h.push2.button_light(ControlName::Convert), colors.button_on);
+    match h.kira_rx.try_recv() {
+        Ok(KiraCommand::Play(req)) => assert!(req.settings.reverse),
+        other => panic!("expected Play, got {:?}", other),
+    }
+
+    h.draw();
+    // Reverse: the arrow moves to the end line and points left.
+    assert_eq!(h.push2.screen().pixel(956, 80), Some(Bgr565::WHITE));
+    assert_ne!(h.push2.screen().pixel(3, 80), Some(Bgr565::WHITE));
+}
+
+#[tokio::test]
+async fn clip_button_switches_encoders_to_the_clip_page() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    h.write_sample(4, 7);
+    let key = h.address(4, 7);
+    h.push2.press_button(ControlName::Select);
+    h.push2.press_pad(4, 7, 100);
+    h.push2.release_button(ControlName::Select);
+    h.step().await;
+    assert_eq!(h.state.edit_page, EditPage::Sample);
+    assert_eq!(h.push2.button_light(ControlName::Clip), 0);
+
+    h.push2.press_button(ControlName::Clip);
+    h.push2.twist_encoder(EncoderName::Track1, 8);
+    h.step().await;
+    assert_eq!(h.state.edit_page, EditPage::Clip);
+    assert_eq!(h.push2.button_light(ControlName::Clip), colors.button_on);
+    assert_eq!(h.state.pad_settings.get(key).pitch_mode, PitchMode::Stretch);
+    assert_eq!(h.state.pad_settings.get(key).volume, 1.0);
+
+    h.push2.press_button(ControlName::Clip);
+    h.push2.twist_encoder(EncoderName::Track1, 8);
+    h.step().await;
+    assert_eq!(h.state.edit_page, EditPage::Sample);
+    assert!(h.state.pad_settings.get(key).volume > 1.0);
+}
+
+#[tokio::test]
+async fn stretch_mode_changes_tempo_without_the_playback_rate() {
+    let mut h = Harness::new();
+    h.write_sample(5, 7);
+    let key = h.address(5, 7);
+    h.push2.press_button(ControlName::Select);
+    h.push2.press_pad(5, 7, 100);
+    h.push2.release_button(ControlName::Select);
+    h.push2.press_button(ControlName::Clip);
+    h.push2.twist_encoder(EncoderName::Track1, 8);
+    h.push2.twist_encoder(EncoderName::Track2, 100);
+    h.step().await;
+    let pad = h.state.pad_settings.get(key);
+    assert_eq!(pad.pitch_mode, PitchMode::Stretch);
+    assert_eq!(pad.tempo, 2.0);
+
+    // The first hit only starts the render; nothing plays until it is ready.
+    h.push2.press_pad(5, 7, 100);
+    h.step().await;
+    assert!(h.kira_rx.try_recv().is_err());
+    assert!(!h.state.playing_pads.contains(&key));
+    assert!(h.state.renders.is_pending(key));
+    h.push2.release_pad(5, 7);
+    h.wait_for_render(key).await;
+    assert!(!h.state.renders.is_pending(key));
+
+    h.push2.press_pad(5, 7, 100);
+    h.step().await;
+    match h.kira_rx.try_recv() {
+        Ok(KiraCommand::Play(req)) => {
+            // Twice as fast: the 0.5 s sample lasts 0.25 s.
+            let seconds = req.sound_data.duration().as_secs_f64();
+            assert!((seconds - 0.25).abs() < 0.01, "{}", seconds);
+        }
+        other => panic!("expected Play, got {:?}", other),
+    }
+
+    // Edits render again once they settle, without another hit.
+    h.push2.twist_encoder(EncoderName::Track2, -40);
+    h.step().await;
+    assert!(h.state.render_cache[&key].0.stretch.tempo > 1.9);
+    tokio::time::sleep(RENDER_SETTLE_DELAY).await;
+    for _ in 0..500 {
+        h.step().await;
+        if h.state.render_cache[&key].0.stretch.tempo < 1.9 {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("the tempo edit was never rendered");
+}
+
+#[tokio::test]
+async fn envelope_encoders_shape_the_played_region() {
+    let mut h = Harness::new();
+    h.write_sample(6, 7);
+    let key = h.address(6, 7);
+    h.push2.press_button(ControlName::Select);
+    h.push2.press_pad(6, 7, 100);
+    h.push2.release_button(ControlName::Select);
+    h.push2.press_button(ControlName::Clip);
+    h.push2.twist_encoder(EncoderName::Track5, 10);
+    h.push2.twist_encoder(EncoderName::Track6, 10);
+    h.push2.twist_encoder(EncoderName::Track7, 10);
+    h.step().await;
+    let envelope = h.state.pad_settings.get(key).envelope;
+    assert_eq!(envelope.attack_ms, 50.0);
+    assert_eq!(envelope.hold_ms, Some(90.0));
+    assert_eq!(envelope.release_ms, 100.0);
+
+    // The very first hit is already faded; nothing waits for a background render.
+    h.push2.press_pad(6, 7, 127);
+    h.step().await;
+    assert!(!h.state.renders.is_pending(key));
+    match h.kira_rx.try_recv() {
+        Ok(KiraCommand::Play(req)) => {
+            // The hold ends the sound after attack + hold + release.
+            let seconds = req.sound_data.duration().as_secs_f64();
+            assert!((seconds - 0.24).abs() < 0.005, "{}", seconds);
+            assert!(req.sound_data.frames[0].left.abs() < 1e-6);
+        }
+        other => panic!("expected Play, got {:?}", other),
+    }
+    assert!(h.state.render_cache.contains_key(&key));
+
+    // Turning the hold below zero switches it off again.
+    h.push2.twist_encoder(EncoderName::Track6, -10);
+    h.step().await;
+    assert_eq!(h.state.pad_settings.get(key).envelope.hold_ms, None);
+
+    h.draw();
+    // Full level sits at the top of the overlay between the attack and the release.
+    let top = Bgr565::new(31, 63, 15);
+    assert_eq!(h.push2.screen().pixel(300, 30), Some(top));
+}
+
+#[tokio::test]
+async fn device_button_opens_the_effects_page() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    h.write_sample(7, 7);
+    let key = h.address(7, 7);
+    h.push2.press_button(ControlName::Select);
+    h.push2.press_pad(7, 7, 100);
+    h.push2.release_button(ControlName::Select);
+    h.push2.press_button(ControlName::Device);
+    h.push2.twist_encoder(EncoderName::Track1, 8);
+    h.push2.twist_encoder(EncoderName::Track2, -8);
+    h.step().await;
+    assert_eq!(h.state.edit_page, EditPage::Effects);
+    assert_eq!(h.push2.button_light(ControlName::Device), colors.button_on);
+    let effects = h.state.pad_settings.get(key).effects;
+    assert_eq!(effects.filter, FilterKind::LowPass);
+    assert!((effects.cutoff_hz - 500.0).abs() < 1e-6);
+
+    // Each edit is sent so a playing pad follows it.
+    let mut last_sent = None;
+    while let Ok(command) = h.kira_rx.try_recv() {
+        if let KiraCommand::SetEffects(pad, sent) = command {
+            assert_eq!(pad, key);
+            last_sent = Some(sent);
+        }
+    }
+    assert_eq!(last_sent, Some(effects));
+
+    h.push2.press_pad(7, 7, 100);
+    h.step().await;
+    match h.kira_rx.try_recv() {
+        Ok(KiraCommand::Play(req)) => assert_eq!(req.effects, effects),
+        other => panic!("expected Play, got {:?}", other),
+    }
+
+    h.push2.press_button(ControlName::Device);
+    h.step().await;
+    assert_eq!(h.state.edit_page, EditPage::Sample);
+    assert_eq!(h.push2.button_light(ControlName::Device), 0);
+}
+
+#[tokio::test]
+async fn setup_page_edits_the_input_gain_and_clip_mode_without_a_selection() {
+    let mut h = Harness::new();
+    let colors = h.state.config.colors;
+    h.push2.press_button(ControlName::Setup);
+    h.push2.twist_encoder(EncoderName::Track1, -24);
+    h.push2.twist_encoder(EncoderName::Track2, 8);
+    h.step().await;
+    assert_eq!(h.state.edit_page, EditPage::Setup);
+    assert_eq!(h.push2.button_light(ControlName::Setup), colors.button_on);
+    assert!((h.state.input_gain_db + 6.0).abs() < 1e-9);
+    assert_eq!(h.state.input_clip_mode, InputClipMode::Limiter);
+
+    match h.audio_rx.try_recv() {
+        Ok(AudioCommand::SetInputGain(gain)) => assert!((gain - 0.501).abs() < 1e-3),
+        other => panic!("expected SetInputGain, got {:?}", other),
+    }
+    assert!(matches!(
+        h.audio_rx.try_recv(),
+        Ok(AudioCommand::SetClipMode(InputClipMode::Limiter))
+    ));
+
+    // The page draws with no pad selected; -6 dB is 18 of the 48 dB range.
+    h.draw();
+    let (gain_bar, _) = h.push2.encoder(0).bar.expect("gain bar drawn");
+    assert_eq!(gain_bar, (0.375 * 127.0) as i32);
+
+    h.push2.press_button(ControlName::Setup);
+    h.step().await;
+    assert_eq!(h.state.edit_page, EditPage::Sample);
+    assert_eq!(h.push2.button_light(ControlName::Setup), 0);
+}
+
+#[tokio::test]
+async fn master_encoder_sets_the_master_gain_without_a_selection() {
+    let mut h = Harness::new();
+    assert_eq!(h.state.selected_for_edit, None);
+    h.push2.twist_encoder(EncoderName::Master, -12);
+    h.step().await;
+    assert_eq!(h.state.master.gain_db(), -6.0);
+
+    // The gain is clamped at the top of its range.
+    h.push2.twist_encoder(EncoderName::Master, 63);
+    h.step().await;
+    assert_eq!(h.state.master.gain_db(), 6.0);
+}
+
+#[tokio::test]
+async fn meters_are_drawn_for_the_master_and_playing_pads() {
+    let mut h = Harness::new();
+    h.write_sample(0, 0);
+    let key = h.address(0, 0);
+    h.draw();
+    // Silence: no meter is drawn.
+    assert!(!h.push2.screen().column_contains(952, Bgr565::GREEN));
+
+    h.state.meters.master.set(0.3, 0.2);
+    h.state.meters.pad(key).set(1.0, 0.1);
+    h.draw();
+    assert!(h.push2.screen().column_contains(952, Bgr565::GREEN));
+    // The pad isn't playing, so only the master meter shows.
+    assert!(!h.push2.screen().column_contains(945, Bgr565::RED));
+
+    h.push2.press_pad(0, 0, 100);
+    h.step().await;
+    h.draw();
+    // A pad meter at full scale shows its peak in red.
+    assert!(h.push2.screen().column_contains(945, Bgr565::RED));
+}
+
+#[tokio::test]
+async fn recording_shows_a_scrolling_input_waveform() {
+    let mut h = Harness::new();
+    let mut engine = CaptureEngine::new(h.state.config.capture, h.state.input_monitor.clone());
+    engine.set_format(CaptureFormat {
+        rate: 48_000,
+        channels: 2,
+    });
+
+    h.push2.press_pad(1, 1, 100);
+    h.step().await;
+    let Ok(start) = h.audio_rx.try_recv() else {
+        panic!("expected Start");
+    };
+    engine.handle_command(start);
+    // 1.5 s of input; the last half second is past full scale.
+    engine.process(&vec![0.5; 96_000]);
+    engine.process(&vec![1.5; 48_000]);
+    h.draw();
+
+    let screen = h.push2.screen();
+    // The newest audio is at the right edge, in red where it clipped.
+    assert!(screen.column_contains(959, Bgr565::RED));
+    assert!(screen.column_contains(900, Bgr565::new(31, 24, 24)));
+    assert!(!screen.column_contains(700, Bgr565::new(31, 24, 24)));
+    assert!(!screen.column_contains(700, Bgr565::RED));
+    // The 150 columns of audio start 150 pixels from the right edge.
+    assert!(screen.column_contains(810, Bgr565::new(31, 24, 24)));
+    assert!(!screen.column_contains(809, Bgr565::new(31, 24, 24)));
+}
+
+#[tokio::test]
+async fn playhead_is_drawn_on_the_selected_waveform() {
+    let mut h = Harness::new();
+    h.write_sample(2, 6);
+    let key = h.address(2, 6);
+    h.push2.press_button(ControlName::Select);
+    h.push2.press_pad(2, 6, 100);
+    h.push2.release_button(ControlName::Select);
+    h.push2.press_pad(2, 6, 100);
+    h.step().await;
+    assert!(h.state.playing_pads.contains(&key));
+
+    // A quarter second into the half-second sample: the middle of the display.
+    h.app_tx.send(AppCommand::Playhead(key, 0.25)).unwrap();
+    h.step().await;
+    h.draw();
+    assert!(h.push2.screen().column_contains(480, Bgr565::YELLOW));
+
+    // Reversed, time played counts back from the end point.
+    h.state.pad_settings.get_mut(key).reverse = true;
+    h.app_tx.send(AppCommand::Playhead(key, 0.125)).unwrap();
+    h.step().await;
+    h.draw();
+    assert!(h.push2.screen().column_contains(720, Bgr565::YELLOW));
+    assert!(!h.push2.screen().column_contains(480, Bgr565::YELLOW));
+
+    let generation = h.state.play_generations[&key];
+    h.app_tx
+        .send(AppCommand::PlaybackFinished(key, generation))
+        .unwrap();
+    h.step().await;
+    h.draw();
+    assert!(!h.push2.screen().column_contains(720, Bgr565::YELLOW));
+}